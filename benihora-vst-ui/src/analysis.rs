@@ -0,0 +1,569 @@
+//! Estimates the controls of Benihora from a speech recording to re-voice it.
+//! The tract is estimated as the tongue only; the constrictions, the velum and the lips are left as they are.
+
+use std::{io, path::Path};
+
+use benihora::{
+    formant::{find_formants, lpc_formants},
+    resample::Resample,
+    tract::TractGeometry,
+    Benihora,
+};
+use rustfft::num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    benihora_managed::BenihoraManaged,
+    score::{Keyframe, Score, Target, Track},
+    synth::Synth,
+    FFT_PLANNER,
+};
+
+/// Length of the analysis window in seconds
+const WINDOW: f32 = 0.04;
+/// Seconds between frames
+const INTERVAL: f32 = 0.01;
+const MIN_FREQUENCY: f32 = 60.0;
+const MAX_FREQUENCY: f32 = 600.0;
+/// Normalized autocorrelation at the period above which a frame is voiced
+const VOICING_THRESHOLD: f32 = 0.45;
+/// Frames quieter than the loudest frame by this many dB are silent
+const DYNAMIC_RANGE: f32 = 40.0;
+/// The formants are estimated at this sample rate
+const LPC_SAMPLE_RATE: f32 = 11025.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Frame {
+    pub voiced: bool,
+    /// Hz, held from the last voiced frame in unvoiced frames
+    pub frequency: f32,
+    pub intensity: f32,
+    pub tenseness: f32,
+    /// Index in the default tract and diameter
+    pub tongue: (f32, f32),
+}
+
+/// Frame-wise controls estimated from a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlTrack {
+    /// Seconds between frames
+    pub interval: f32,
+    pub frames: Vec<Frame>,
+}
+
+/// Reads a PCM or float WAV file. Multichannel files are mixed down to mono.
+/// Returns the samples and the sample rate.
+pub fn read_wav(path: impl AsRef<Path>) -> io::Result<(Vec<f32>, f32)> {
+    parse_wav(&std::fs::read(path)?)
+}
+
+pub fn parse_wav(bytes: &[u8]) -> io::Result<(Vec<f32>, f32)> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_owned());
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("not a WAV file"));
+    }
+
+    // (format tag, channels, sample rate, bits per sample)
+    let mut format = None;
+    let mut position = 12;
+    while position + 8 <= bytes.len() {
+        let id = &bytes[position..position + 4];
+        let size = u32::from_le_bytes(bytes[position + 4..position + 8].try_into().unwrap());
+        let body = &bytes[position + 8..(position + 8 + size as usize).min(bytes.len())];
+        match id {
+            b"fmt " => {
+                if body.len() < 16 {
+                    return Err(invalid("broken fmt chunk"));
+                }
+                let u16_at = |i: usize| u16::from_le_bytes([body[i], body[i + 1]]);
+                let mut tag = u16_at(0);
+                // WAVE_FORMAT_EXTENSIBLE has the actual tag in the sub format GUID
+                if tag == 0xfffe && body.len() >= 26 {
+                    tag = u16_at(24);
+                }
+                let sample_rate = u32::from_le_bytes(body[4..8].try_into().unwrap());
+                format = Some((tag, u16_at(2) as usize, sample_rate as f32, u16_at(14)));
+            }
+            b"data" => {
+                let (tag, channels, sample_rate, bits) =
+                    format.ok_or_else(|| invalid("data chunk before fmt chunk"))?;
+                let decode: fn(&[u8]) -> f32 = match (tag, bits) {
+                    (1, 8) => |b| (b[0] as f32 - 128.0) / 128.0,
+                    (1, 16) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+                    (1, 24) => |b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2147483648.0,
+                    (1, 32) => {
+                        |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2147483648.0
+                    }
+                    (3, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+                    _ => return Err(invalid("unsupported sample format")),
+                };
+                if channels == 0 {
+                    return Err(invalid("no channels"));
+                }
+                let width = bits as usize / 8;
+                let signal = body
+                    .chunks_exact(width * channels)
+                    .map(|frame| {
+                        frame.chunks_exact(width).map(decode).sum::<f32>() / channels as f32
+                    })
+                    .collect();
+                return Ok((signal, sample_rate));
+            }
+            _ => {}
+        }
+        // Chunks are padded to even sizes
+        position += 8 + size as usize + size as usize % 2;
+    }
+    Err(invalid("no data chunk"))
+}
+
+struct Measurement {
+    level: f32,
+    periodicity: f32,
+    frequency: f32,
+    h1_h2: f32,
+    formants: Option<(f32, f32)>,
+}
+
+/// Estimates f0, voicing, intensity, tenseness and the tongue every 10 ms.
+pub fn analyze(signal: &[f32], sample_rate: f32) -> ControlTrack {
+    let window_length = (WINDOW * sample_rate) as usize;
+    let hop = (INTERVAL * sample_rate) as usize;
+    // The pitch range needs lags of at least 2 samples
+    if signal.len() < window_length || hop == 0 || sample_rate < 2.0 * MAX_FREQUENCY {
+        return ControlTrack {
+            interval: INTERVAL,
+            frames: Vec::new(),
+        };
+    }
+    let fft_length = (window_length * 2).next_power_of_two();
+    let (fft, ifft) = FFT_PLANNER.with(|planner| {
+        let mut planner = planner.borrow_mut();
+        (
+            planner.plan_fft_forward(fft_length),
+            planner.plan_fft_inverse(fft_length),
+        )
+    });
+    let window: Vec<f32> = (0..window_length)
+        .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / window_length as f32).cos())
+        .collect();
+    let autocorrelation = |spectrum: &mut Vec<Complex32>| {
+        for x in spectrum.iter_mut() {
+            *x = Complex32::from(x.norm_sqr());
+        }
+        ifft.process(spectrum);
+    };
+    // The autocorrelation of the window compensates the taper (Boersma 1993)
+    let mut window_autocorrelation: Vec<Complex32> =
+        window.iter().map(|&w| Complex32::from(w)).collect();
+    window_autocorrelation.resize(fft_length, Complex32::default());
+    fft.process(&mut window_autocorrelation);
+    autocorrelation(&mut window_autocorrelation);
+
+    let lpc_signal = downsample(signal, sample_rate, LPC_SAMPLE_RATE);
+    let lpc_window_length = (WINDOW * LPC_SAMPLE_RATE) as usize;
+
+    let mut measurements = Vec::new();
+    for start in (0..=signal.len() - window_length).step_by(hop) {
+        let frame = &signal[start..start + window_length];
+        let mean = frame.iter().sum::<f32>() / frame.len() as f32;
+        let mut spectrum: Vec<Complex32> = frame
+            .iter()
+            .zip(&window)
+            .map(|(x, w)| Complex32::from((x - mean) * w))
+            .collect();
+        spectrum.resize(fft_length, Complex32::default());
+        fft.process(&mut spectrum);
+
+        let power: Vec<f32> = spectrum.iter().map(|x| x.norm_sqr()).collect();
+
+        let mut correlation = spectrum;
+        autocorrelation(&mut correlation);
+        let r0 = correlation[0].re;
+        let level = 10.0 * (r0 / fft_length as f32 / window_length as f32 + 1e-20).log10();
+
+        // Autocorrelation peaks in the pitch range
+        let normalized = |lag: usize| {
+            correlation[lag].re / window_autocorrelation[lag].re * window_autocorrelation[0].re
+                / r0.max(1e-20)
+        };
+        let lags = (sample_rate / MAX_FREQUENCY) as usize
+            ..((sample_rate / MIN_FREQUENCY) as usize).min(window_length / 2);
+        let mut peaks = Vec::new();
+        for lag in lags {
+            let (a, b, c) = (normalized(lag - 1), normalized(lag), normalized(lag + 1));
+            if a < b && c <= b {
+                let curvature = a - 2.0 * b + c;
+                let offset = if curvature < 0.0 {
+                    (0.5 * (a - c) / curvature).clamp(-0.5, 0.5)
+                } else {
+                    0.0
+                };
+                peaks.push((b, sample_rate / (lag as f32 + offset)));
+            }
+        }
+        // The multiples of the period have peaks as high as the period itself,
+        // so the shortest lag close to the highest peak is taken
+        let highest = peaks.iter().map(|p| p.0).fold(0.0, f32::max);
+        let (periodicity, frequency) = peaks
+            .into_iter()
+            .find(|p| highest * 0.9 <= p.0)
+            .unwrap_or((0.0, 0.0));
+
+        // Level difference of the first two harmonics, which is large for a breathy voice
+        let harmonic = |frequency: f32| {
+            let bin = (frequency / sample_rate * fft_length as f32).round() as usize;
+            let width = (0.2 * frequency / sample_rate * fft_length as f32) as usize + 1;
+            power[bin.saturating_sub(width)..(bin + width + 1).min(fft_length / 2)]
+                .iter()
+                .fold(1e-20, |a, &b| f32::max(a, b))
+        };
+        let h1_h2 = if 0.0 < frequency {
+            10.0 * (harmonic(frequency) / harmonic(2.0 * frequency)).log10()
+        } else {
+            0.0
+        };
+
+        let lpc_start = (start as f32 * LPC_SAMPLE_RATE / sample_rate) as usize;
+        let formants = lpc_signal
+            .get(lpc_start..lpc_start + lpc_window_length)
+            .map(|frame| lpc_formants(frame, LPC_SAMPLE_RATE, 12))
+            .filter(|formants| formants.len() >= 2)
+            .map(|formants| (formants[0].frequency, formants[1].frequency));
+
+        measurements.push(Measurement {
+            level,
+            periodicity,
+            frequency,
+            h1_h2,
+            formants,
+        });
+    }
+
+    let max_level = measurements
+        .iter()
+        .map(|m| m.level)
+        .fold(f32::NEG_INFINITY, f32::max);
+    let voiced =
+        |m: &Measurement| VOICING_THRESHOLD < m.periodicity && max_level - DYNAMIC_RANGE < m.level;
+
+    let codebook = TongueCodebook::get();
+    let vowel_space = VowelSpace::new(
+        measurements
+            .iter()
+            .filter(|m| voiced(m))
+            .filter_map(|m| m.formants),
+    );
+
+    let mut frames: Vec<Frame> = Vec::with_capacity(measurements.len());
+    let mut frequency = measurements
+        .iter()
+        .find(|m| voiced(m))
+        .map(|m| m.frequency)
+        .unwrap_or(140.0);
+    let mut tongue = benihora::tract::DEFAULT_TONGUE;
+    for m in &measurements {
+        let voiced = voiced(m);
+        if voiced {
+            frequency = m.frequency;
+            if let (Some(formants), Some(vowel_space)) = (m.formants, &vowel_space) {
+                tongue = codebook.nearest(vowel_space.normalize(formants));
+            }
+        }
+        frames.push(Frame {
+            voiced,
+            frequency,
+            intensity: ((m.level - max_level + DYNAMIC_RANGE) / DYNAMIC_RANGE).clamp(0.0, 1.0),
+            // H1-H2 is about 12 dB for a breathy voice and 2 dB for a pressed voice
+            tenseness: ((12.0 - m.h1_h2) / 10.0).clamp(0.0, 1.0),
+            tongue,
+        });
+    }
+
+    ControlTrack {
+        interval: hop as f32 / sample_rate,
+        frames,
+    }
+}
+
+/// Renders the track with the voice settings of `synth`.
+pub fn resynthesize(track: &ControlTrack, synth: &Synth, sample_rate: f32) -> Vec<f32> {
+    let mut benihora = BenihoraManaged::new(synth.sound_speed, sample_rate, 1.0, synth.seed);
+    let length = (track.frames.len() as f32 * track.interval * sample_rate).round() as usize;
+    let mut current = None;
+    (0..length)
+        .map(|i| {
+            let index =
+                ((i as f32 / sample_rate / track.interval) as usize).min(track.frames.len() - 1);
+            if current != Some(index) {
+                track.apply(index, current, &mut benihora);
+                current = Some(index);
+            }
+            benihora.process(&synth.benihora_params)
+        })
+        .collect()
+}
+
+impl ControlTrack {
+    /// Converts the track into a score to play with `Synth::play_score`. The unvoiced frames are silent.
+    pub fn to_score(&self, name: String) -> Score {
+        let track = |target, value: fn(&Frame) -> f32| Track {
+            target,
+            keyframes: self
+                .frames
+                .iter()
+                .enumerate()
+                .map(|(i, frame)| Keyframe {
+                    time: i as f32 * self.interval,
+                    value: value(frame),
+                    curve: Default::default(),
+                })
+                .collect(),
+        };
+        Score {
+            name,
+            tracks: vec![
+                track(Target::Frequency, |f| f.frequency),
+                track(
+                    Target::Intensity,
+                    |f| if f.voiced { f.intensity } else { 0.0 },
+                ),
+                track(Target::Tenseness, |f| f.tenseness),
+                track(Target::TongueIndex, |f| f.tongue.0),
+                track(Target::TongueDiameter, |f| f.tongue.1),
+            ],
+        }
+    }
+
+    pub(crate) fn apply(&self, index: usize, last: Option<usize>, benihora: &mut BenihoraManaged) {
+        let frame = &self.frames[index];
+        let was_voiced = last.map(|i| self.frames[i].voiced).unwrap_or(false);
+        benihora.sound = frame.voiced;
+        benihora.frequency.set(frame.frequency, !was_voiced);
+        benihora.intensity_target = Some(frame.intensity);
+        benihora.set_tenseness(frame.tenseness);
+        let source = &benihora.benihora.tract.source;
+        benihora.tract.tongue_target = (source.scale_index(frame.tongue.0), frame.tongue.1);
+    }
+}
+
+fn downsample(signal: &[f32], sample_rate: f32, target_sample_rate: f32) -> Vec<f32> {
+    let mut resample = Resample::new_sinc(sample_rate, target_sample_rate, 16);
+    let latency = resample.latency().round() as usize;
+    let length = (signal.len() as f32 * target_sample_rate / sample_rate) as usize;
+    let mut input = signal.iter().copied().chain(std::iter::repeat(0.0));
+    (0..length + latency)
+        .map(|_| resample.process(|| input.next().unwrap()))
+        .skip(latency)
+        .collect()
+}
+
+/// Log F1 and F2 of the tongue positions of Benihora
+struct TongueCodebook {
+    entries: Vec<((f32, f32), (f32, f32))>,
+    space: VowelSpace,
+}
+
+impl TongueCodebook {
+    /// Built on the first use, as it renders many tract shapes
+    fn get() -> &'static Self {
+        static CODEBOOK: std::sync::OnceLock<TongueCodebook> = std::sync::OnceLock::new();
+        CODEBOOK.get_or_init(Self::new)
+    }
+
+    fn new() -> Self {
+        let mut benihora = Benihora::new(3.0, 48000.0, 1.0, 0, false, &TractGeometry::default());
+        let mut entries = Vec::new();
+        for i in 0..=12 {
+            for j in 0..=8 {
+                let tract = &mut benihora.tract;
+                tract.source.tongue = tract
+                    .source
+                    .tongue_clamp(12.0 + 17.0 * i as f32 / 12.0, 2.05 + 1.45 * j as f32 / 8.0);
+                tract.update_diameter();
+                tract.current_diameter = tract.target_diameter.clone();
+                tract.update_block(0.0);
+                let (response, sample_rate) = benihora::tract_impulse_response(2048, &benihora);
+                let formants = find_formants(&response, sample_rate, 4000.0);
+                if formants.len() >= 2 {
+                    entries.push((
+                        benihora.tract.source.tongue,
+                        (formants[0].frequency, formants[1].frequency),
+                    ));
+                }
+            }
+        }
+        let space = VowelSpace::new(entries.iter().map(|(_, formants)| *formants)).unwrap();
+        Self { entries, space }
+    }
+
+    /// The tongue whose normalized formants are the closest
+    fn nearest(&self, target: (f32, f32)) -> (f32, f32) {
+        let distance = |formants: (f32, f32)| {
+            let (x, y) = self.space.normalize(formants);
+            (x - target.0).powi(2) + (y - target.1).powi(2)
+        };
+        self.entries
+            .iter()
+            .min_by(|a, b| distance(a.1).total_cmp(&distance(b.1)))
+            .unwrap()
+            .0
+    }
+}
+
+/// Mean and standard deviation of log F1 and F2 to compare the vowels of different speakers
+/// (Lobanov 1971). The formants of the tract model are not in the range of real speakers.
+struct VowelSpace {
+    mean: (f32, f32),
+    deviation: (f32, f32),
+}
+
+impl VowelSpace {
+    fn new(formants: impl Iterator<Item = (f32, f32)>) -> Option<Self> {
+        let logs: Vec<(f32, f32)> = formants.map(|(f1, f2)| (f1.ln(), f2.ln())).collect();
+        if logs.len() < 2 {
+            return None;
+        }
+        let n = logs.len() as f32;
+        let mean = (
+            logs.iter().map(|x| x.0).sum::<f32>() / n,
+            logs.iter().map(|x| x.1).sum::<f32>() / n,
+        );
+        let deviation = (
+            (logs.iter().map(|x| (x.0 - mean.0).powi(2)).sum::<f32>() / n).sqrt(),
+            (logs.iter().map(|x| (x.1 - mean.1).powi(2)).sum::<f32>() / n).sqrt(),
+        );
+        Some(Self {
+            mean,
+            deviation: (deviation.0.max(1e-3), deviation.1.max(1e-3)),
+        })
+    }
+
+    fn normalize(&self, (f1, f2): (f32, f32)) -> (f32, f32) {
+        (
+            (f1.ln() - self.mean.0) / self.deviation.0,
+            (f2.ln() - self.mean.1) / self.deviation.1,
+        )
+    }
+}
+
+/// A WAV file of one fmt chunk and one data chunk
+#[cfg(test)]
+fn wav(fmt: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+    for (id, body) in [(b"fmt ", fmt), (b"data", data)] {
+        bytes.extend_from_slice(id);
+        bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(body);
+    }
+    bytes
+}
+
+#[cfg(test)]
+fn fmt(tag: u16, channels: u16, bits: u16) -> Vec<u8> {
+    let block = channels * bits / 8;
+    let mut fmt = Vec::new();
+    fmt.extend_from_slice(&tag.to_le_bytes());
+    fmt.extend_from_slice(&channels.to_le_bytes());
+    fmt.extend_from_slice(&8000u32.to_le_bytes());
+    fmt.extend_from_slice(&(8000 * block as u32).to_le_bytes());
+    fmt.extend_from_slice(&block.to_le_bytes());
+    fmt.extend_from_slice(&bits.to_le_bytes());
+    fmt
+}
+
+#[test]
+fn test_parse_wav() {
+    let data: Vec<u8> = [0x4000i16, -0x4000]
+        .iter()
+        .flat_map(|x| x.to_le_bytes())
+        .collect();
+    let (signal, sample_rate) = parse_wav(&wav(&fmt(1, 1, 16), &data)).unwrap();
+    assert_eq!((signal, sample_rate), (vec![0.5, -0.5], 8000.0));
+    // The channels are mixed down
+    let (signal, _) = parse_wav(&wav(&fmt(1, 2, 16), &data)).unwrap();
+    assert_eq!(signal, vec![0.0]);
+
+    let data = [0x00, 0x00, 0x40, 0x00, 0x00, 0xc0];
+    let (signal, _) = parse_wav(&wav(&fmt(1, 1, 24), &data)).unwrap();
+    assert_eq!(signal, vec![0.5, -0.5]);
+
+    let data: Vec<u8> = [0.25f32, -1.0]
+        .iter()
+        .flat_map(|x| x.to_le_bytes())
+        .collect();
+    let (signal, _) = parse_wav(&wav(&fmt(3, 1, 32), &data)).unwrap();
+    assert_eq!(signal, vec![0.25, -1.0]);
+
+    // WAVE_FORMAT_EXTENSIBLE with the float sub format
+    let mut extensible = fmt(0xfffe, 1, 32);
+    extensible.extend_from_slice(&22u16.to_le_bytes());
+    extensible.extend_from_slice(&32u16.to_le_bytes());
+    extensible.extend_from_slice(&4u32.to_le_bytes());
+    extensible.extend_from_slice(&3u16.to_le_bytes());
+    extensible.extend_from_slice(&[0; 14]);
+    let (signal, _) = parse_wav(&wav(&extensible, &data)).unwrap();
+    assert_eq!(signal, vec![0.25, -1.0]);
+
+    assert!(parse_wav(b"RIFF").is_err());
+    assert!(parse_wav(b"RIFX\0\0\0\0WAVE").is_err());
+    assert!(parse_wav(&wav(&fmt(1, 1, 16)[..12], &[])).is_err());
+    assert!(parse_wav(&wav(&fmt(1, 1, 12), &[0; 4])).is_err());
+    assert!(parse_wav(&wav(&fmt(1, 0, 16), &[0; 4])).is_err());
+    let mut no_fmt = b"RIFF\0\0\0\0WAVEdata".to_vec();
+    no_fmt.extend_from_slice(&[2, 0, 0, 0, 0, 0]);
+    assert!(parse_wav(&no_fmt).is_err());
+    let mut no_data = wav(&fmt(1, 1, 16), &[]);
+    no_data.truncate(no_data.len() - 8);
+    assert!(parse_wav(&no_data).is_err());
+}
+
+#[test]
+fn test_analyze() {
+    // Half a second of a 150 Hz buzz, then silence
+    let sample_rate = 16000.0;
+    let signal: Vec<f32> = (0..12000)
+        .map(|i| {
+            if 8000 <= i {
+                return 0.0;
+            }
+            let phase = std::f32::consts::TAU * 150.0 * i as f32 / sample_rate;
+            (1..=10)
+                .map(|k| (k as f32 * phase).sin() / k as f32)
+                .sum::<f32>()
+                * 0.3
+        })
+        .collect();
+    let track = analyze(&signal, sample_rate);
+    assert_eq!(track.interval, 0.01);
+    // Too low a sample rate for the pitch range
+    assert!(analyze(&signal, 1000.0).frames.is_empty());
+
+    let voiced = &track.frames[5..40];
+    assert!(voiced.iter().all(|f| f.voiced));
+    assert!(voiced.iter().all(|f| (f.frequency - 150.0).abs() < 3.0));
+    assert!(voiced.iter().all(|f| 0.9 < f.intensity));
+    assert!(track.frames[55..].iter().all(|f| !f.voiced));
+
+    let score = track.to_score(String::new());
+    assert!((score.duration() - (track.frames.len() - 1) as f32 * 0.01).abs() < 1e-4);
+}
+
+#[test]
+fn test_resynthesize() {
+    let frame = Frame {
+        voiced: true,
+        frequency: 200.0,
+        intensity: 0.8,
+        tenseness: 0.6,
+        tongue: benihora::tract::DEFAULT_TONGUE,
+    };
+    let track = ControlTrack {
+        interval: 0.01,
+        frames: vec![frame; 20],
+    };
+    let signal = resynthesize(&track, &Synth::new(), 8000.0);
+    assert_eq!(signal.len(), 1600);
+    assert!(signal.iter().all(|x| x.is_finite()));
+    assert!(signal.iter().any(|x| x.abs() > 1e-3));
+}
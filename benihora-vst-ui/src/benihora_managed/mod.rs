@@ -4,10 +4,13 @@ mod tract;
 use std::f32::consts::TAU;
 
 use benihora::{
+    articulator::Articulators,
+    glottis::VoiceQuality,
     lerp,
     managed::{Loudness, Tenseness},
+    tract::{Movement, TractGeometry},
     wiggle::Wiggle,
-    Benihora, IntervalTimer,
+    Benihora, Buses, IntervalTimer,
 };
 use serde::{Deserialize, Serialize};
 
@@ -20,6 +23,20 @@ pub struct BenihoraManaged {
     intensity_adsr: IntensityAdsr,
     intensity_pid: IntensityPid,
     pub intensity_pid_enabled: bool,
+    /// Overrides `Params::noteon_intensity` when set, e.g. by a breath controller
+    pub intensity_target: Option<f32>,
+    /// Overrides `intensity_target` and `Params::noteon_intensity` when set by a routine
+    pub routine_intensity: Option<f32>,
+    /// Overrides `Params::aspiration_level` when set, e.g. by a routine
+    pub aspiration_level: Option<f32>,
+    /// Overrides `Params::vibrato_amount` when set
+    pub vibrato_amount: Option<f32>,
+    /// Overrides `Params::vibrato_rate` when set. The tempo sync takes precedence.
+    pub vibrato_rate: Option<f32>,
+    intensity_override: f32,
+    /// `spring_movement`, `articulator_speed` and `articulator_damping` of the params
+    /// that the movement of the tract was made from
+    movement_params: Option<(bool, f32, f32)>,
     pub loudness: Loudness,
     pub tract: tract::Tract,
     pub benihora: Benihora,
@@ -30,9 +47,39 @@ pub struct BenihoraManaged {
     pub history_count: usize,
     pub level: f32,
     pub waveform_recorder: WaveformRecorder,
+    pub transport: Transport,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Tempo and position of the song
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transport {
+    /// BPM
+    pub tempo: f32,
+    /// Quarter notes from the start of the song. None while the host is stopped.
+    pub position: Option<f64>,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self {
+            tempo: 120.0,
+            position: None,
+        }
+    }
+}
+
+impl Transport {
+    /// Advances the position by `dtime` seconds and returns the elapsed beats.
+    pub fn advance(&mut self, dtime: f32) -> f32 {
+        let dbeats = dtime * self.tempo / 60.0;
+        if let Some(position) = &mut self.position {
+            *position += dbeats as f64;
+        }
+        dbeats
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Params {
     pub always_sound: bool,
     pub frequency_pid: pid_controller::PIDParam,
@@ -42,8 +89,39 @@ pub struct Params {
     pub frequency_wobble_amount: f32,
     pub vibrato_amount: f32,
     pub vibrato_rate: f32,
+    /// Runs the vibrato at `vibrato_division` instead of `vibrato_rate`
+    #[serde(default)]
+    pub vibrato_sync: bool,
+    /// Beats per vibrato cycle, e.g. 0.25 for sixteenth notes
+    #[serde(default = "default_vibrato_division")]
+    pub vibrato_division: f32,
     pub tenseness_wobble_amount: f32,
     pub aspiration_level: f32,
+    #[serde(default)]
+    pub jitter: f32,
+    #[serde(default)]
+    pub shimmer: f32,
+    #[serde(default)]
+    pub creak: f32,
+    #[serde(default)]
+    pub breath_leak: f32,
+    /// Moves the tract by `Movement::Spring` instead of at a constant speed
+    #[serde(default)]
+    pub spring_movement: bool,
+    /// Multiplier of the spring frequencies
+    #[serde(default = "default_articulator_scale")]
+    pub articulator_speed: f32,
+    /// Multiplier of the spring damping ratios
+    #[serde(default = "default_articulator_scale")]
+    pub articulator_damping: f32,
+}
+
+fn default_vibrato_division() -> f32 {
+    0.25
+}
+
+fn default_articulator_scale() -> f32 {
+    1.0
 }
 
 impl Default for Params {
@@ -57,8 +135,17 @@ impl Default for Params {
             frequency_wobble_amount: 0.1,
             vibrato_amount: 0.005,
             vibrato_rate: 6.0,
+            vibrato_sync: false,
+            vibrato_division: 0.25,
             tenseness_wobble_amount: 1.0,
             aspiration_level: 1.0,
+            jitter: 0.0,
+            shimmer: 0.0,
+            creak: 0.0,
+            breath_leak: 0.0,
+            spring_movement: false,
+            articulator_speed: 1.0,
+            articulator_damping: 1.0,
         }
     }
 }
@@ -66,6 +153,14 @@ impl Default for Params {
 impl BenihoraManaged {
     pub fn new(sound_speed: f32, sample_rate: f32, over_sample: f32, seed: u32) -> Self {
         let interval = 0.02;
+        let benihora = Benihora::new(
+            sound_speed,
+            sample_rate,
+            over_sample,
+            seed,
+            false,
+            &TractGeometry::default(),
+        );
         Self {
             sound: false,
             frequency: Frequency::new(interval, seed, 140.0, 1.0 / interval),
@@ -73,9 +168,16 @@ impl BenihoraManaged {
             intensity_pid: IntensityPid::new(sample_rate),
             intensity_adsr: IntensityAdsr::new(sample_rate),
             intensity_pid_enabled: false,
+            intensity_target: None,
+            routine_intensity: None,
+            aspiration_level: None,
+            vibrato_amount: None,
+            vibrato_rate: None,
+            intensity_override: 0.0,
+            movement_params: None,
             loudness: Loudness::new(0.6f32.powf(0.25)),
-            tract: tract::Tract::new(),
-            benihora: Benihora::new(sound_speed, sample_rate, over_sample, seed, false),
+            tract: tract::Tract::new(benihora.tract.source.tongue),
+            benihora,
             update_timer: IntervalTimer::new_overflowed(interval),
             sample_rate,
             dtime: 1.0 / sample_rate,
@@ -83,6 +185,7 @@ impl BenihoraManaged {
             history_count: 0,
             level: 0.0,
             waveform_recorder: WaveformRecorder::new(),
+            transport: Transport::default(),
         }
     }
 
@@ -92,6 +195,14 @@ impl BenihoraManaged {
         self.loudness.target = tenseness.powf(0.25);
     }
 
+    /// Drops the overrides of the routines. `intensity_target` belongs to the controllers and is kept.
+    pub fn reset_overrides(&mut self) {
+        self.routine_intensity = None;
+        self.aspiration_level = None;
+        self.vibrato_amount = None;
+        self.vibrato_rate = None;
+    }
+
     pub fn get_intensity(&self) -> f32 {
         if self.intensity_pid_enabled {
             self.intensity_pid.get()
@@ -101,31 +212,107 @@ impl BenihoraManaged {
     }
 
     pub fn process(&mut self, params: &Params) -> f32 {
+        self.process_with(params, Benihora::process)
+    }
+
+    /// Same as `process` but returns the mouth, nose, and glottal source signals separately.
+    pub fn process_buses(&mut self, params: &Params) -> Buses {
+        self.process_with(params, Benihora::process_buses)
+    }
+
+    /// Same as `process` but the tract is excited by `excitation` instead of the glottis.
+    pub fn process_excitation(&mut self, params: &Params, excitation: f32) -> f32 {
+        self.process_with(params, |benihora, _, _, intensity, _, _| {
+            benihora.process_excitation(excitation, intensity)
+        })
+    }
+
+    /// Same as `process_excitation` but returns the buses.
+    pub fn process_excitation_buses(&mut self, params: &Params, excitation: f32) -> Buses {
+        self.process_with(params, |benihora, _, _, intensity, _, _| {
+            benihora.process_excitation_buses(excitation, intensity)
+        })
+    }
+
+    fn process_with<T>(
+        &mut self,
+        params: &Params,
+        process: impl FnOnce(&mut Benihora, f32, f32, f32, f32, f32) -> T,
+    ) -> T {
         self.tenseness.wobble_amount = params.tenseness_wobble_amount;
+        self.benihora.set_voice_quality(VoiceQuality {
+            jitter: params.jitter,
+            shimmer: params.shimmer,
+            creak: params.creak,
+            breath_leak: params.breath_leak,
+        });
+        let movement_params = (
+            params.spring_movement,
+            params.articulator_speed,
+            params.articulator_damping,
+        );
+        if self.movement_params != Some(movement_params) {
+            self.movement_params = Some(movement_params);
+            let movement = if params.spring_movement {
+                Movement::Spring(
+                    Articulators::natural()
+                        .scaled(params.articulator_speed, params.articulator_damping),
+                )
+            } else {
+                Movement::Linear
+            };
+            if *self.benihora.tract.movement() != movement {
+                self.benihora.tract.set_movement(movement);
+            }
+        }
 
         if self.update_timer.overflowed() {
+            let vibrato_rate = if params.vibrato_sync {
+                let division = params.vibrato_division.max(1.0 / 64.0);
+                if let Some(position) = self.transport.position {
+                    // Lock the phase to the song
+                    self.frequency.phase = (position / division as f64).rem_euclid(1.0) as f32;
+                }
+                self.transport.tempo / 60.0 / division
+            } else {
+                self.vibrato_rate.unwrap_or(params.vibrato_rate)
+            };
             self.frequency.update(
                 self.update_timer.interval,
                 params.frequency_wobble_amount,
-                params.vibrato_amount,
-                params.vibrato_rate,
+                self.vibrato_amount.unwrap_or(params.vibrato_amount),
+                vibrato_rate,
                 &params.frequency_pid,
             );
             self.tenseness.update();
-            self.tract.update(
-                self.update_timer.interval,
-                &mut self.benihora.tract.source.tongue,
-            );
+            if params.spring_movement {
+                // The springs of the tract move the tongue
+                self.benihora.tract.source.tongue = self.tract.tongue_target;
+            } else {
+                self.tract.update(
+                    self.update_timer.interval,
+                    &mut self.benihora.tract.source.tongue,
+                );
+            }
             self.benihora.tract.update_diameter();
         }
         let lambda = self.update_timer.progress();
         self.update_timer.update(self.dtime);
 
+        let noteon_intensity =
+            if let Some(target) = self.routine_intensity.or(self.intensity_target) {
+                // Smooth the steps of 7-bit controllers
+                self.intensity_override +=
+                    (target - self.intensity_override) * (self.dtime / 0.01).min(1.0);
+                self.intensity_override
+            } else {
+                params.noteon_intensity
+            };
         let intensity = if self.intensity_pid_enabled {
             self.intensity_pid.process(
                 &params.intensity_pid,
                 if self.sound | params.always_sound {
-                    params.noteon_intensity
+                    noteon_intensity
                 } else {
                     0.0
                 },
@@ -133,7 +320,7 @@ impl BenihoraManaged {
         } else {
             self.intensity_adsr
                 .process(&params.intensity_adsr, self.sound | params.always_sound)
-                * params.noteon_intensity
+                * noteon_intensity
         };
         let frequency = self.frequency.get(lambda);
         let tenseness = self.tenseness.get(lambda);
@@ -156,12 +343,13 @@ impl BenihoraManaged {
         self.history_count -= 1;
         self.level += self.benihora.get_glottal_output().powi(2) as f32;
 
-        let y = self.benihora.process(
+        let y = process(
+            &mut self.benihora,
             frequency,
             tenseness,
             intensity,
             loudness,
-            params.aspiration_level,
+            self.aspiration_level.unwrap_or(params.aspiration_level),
         );
 
         self.waveform_recorder.record(
@@ -1,14 +1,12 @@
-use benihora::tract::DEFAULT_TONGUE;
-
 pub struct Tract {
     pub tongue_target: (f32, f32),
     pub speed: f32,
 }
 
 impl Tract {
-    pub fn new() -> Self {
+    pub fn new(tongue_target: (f32, f32)) -> Self {
         Self {
-            tongue_target: DEFAULT_TONGUE,
+            tongue_target,
             speed: 20.0,
         }
     }
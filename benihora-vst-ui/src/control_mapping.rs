@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ControlMapping {
+    pub source: Source,
+    pub target: Target,
+    /// Target value at controller value 0
+    pub min: f32,
+    /// Target value at controller value 1
+    pub max: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    ControlChange(u8),
+    ChannelPressure,
+    PolyPressure,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Tenseness,
+    /// Replaces the noteon intensity while the note is held, e.g. from a breath controller
+    Intensity,
+    TongueX,
+    TongueY,
+    /// 0: closed, 1: open
+    Velum,
+    /// Strength of the constriction, 0: released, 1: closed
+    Constriction(usize),
+}
+
+impl ControlMapping {
+    pub fn new(source: Source, target: Target) -> Self {
+        let (min, max) = target.default_range();
+        Self {
+            source,
+            target,
+            min,
+            max,
+        }
+    }
+
+    /// Maps a controller value in 0..=1 to the target value.
+    pub fn map(&self, value: f32) -> f32 {
+        benihora::lerp(self.min, self.max, value.clamp(0.0, 1.0))
+    }
+}
+
+impl Source {
+    pub fn name(&self) -> String {
+        match self {
+            Source::ControlChange(cc) => format!("CC {}", cc),
+            Source::ChannelPressure => "Channel pressure".to_string(),
+            Source::PolyPressure => "Poly pressure".to_string(),
+        }
+    }
+}
+
+impl Target {
+    pub const ALL: [Target; 6] = [
+        Target::Tenseness,
+        Target::Intensity,
+        Target::TongueX,
+        Target::TongueY,
+        Target::Velum,
+        Target::Constriction(0),
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            Target::Tenseness => "Tenseness".to_string(),
+            Target::Intensity => "Intensity".to_string(),
+            Target::TongueX => "Tongue x".to_string(),
+            Target::TongueY => "Tongue y".to_string(),
+            Target::Velum => "Velum".to_string(),
+            Target::Constriction(i) => format!("Constriction {}", i + 1),
+        }
+    }
+
+    pub fn default_range(&self) -> (f32, f32) {
+        match self {
+            Target::TongueX => (12.0, 28.0),
+            Target::TongueY => (2.0, 4.0),
+            _ => (0.0, 1.0),
+        }
+    }
+}
+
+pub fn default_control_mappings() -> Vec<ControlMapping> {
+    vec![ControlMapping::new(
+        Source::ControlChange(2), // Breath controller
+        Target::Intensity,
+    )]
+}
+
+#[test]
+fn test_map() {
+    let mapping = ControlMapping::new(Source::ControlChange(1), Target::TongueX);
+    assert_eq!((mapping.min, mapping.max), (12.0, 28.0));
+    assert_eq!(mapping.map(0.0), 12.0);
+    assert_eq!(mapping.map(0.5), 20.0);
+    assert_eq!(mapping.map(1.0), 28.0);
+    // Out of range controller values are clamped
+    assert_eq!(mapping.map(-1.0), 12.0);
+    assert_eq!(mapping.map(2.0), 28.0);
+
+    // An inverted range maps the other way
+    let mapping = ControlMapping {
+        min: 1.0,
+        max: 0.0,
+        ..ControlMapping::new(Source::ChannelPressure, Target::Velum)
+    };
+    assert_eq!(mapping.map(0.0), 1.0);
+    assert_eq!(mapping.map(0.25), 0.75);
+    assert_eq!(mapping.map(1.0), 0.0);
+}
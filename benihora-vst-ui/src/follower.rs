@@ -0,0 +1,44 @@
+use benihora::pitch::PitchTracker;
+
+/// Follows the envelope and the pitch of the audio input
+pub struct Follower {
+    envelope: f32,
+    tracker: PitchTracker,
+}
+
+/// Below this level the input is silent and has no pitch
+const SILENCE: f32 = 0.001;
+/// Pitches detected with less confidence are ignored, e.g. breath noise and consonants
+const MIN_CONFIDENCE: f32 = 0.8;
+const MIN_FREQUENCY: f32 = 50.0;
+const MAX_FREQUENCY: f32 = 1000.0;
+
+impl Follower {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            envelope: 0.0,
+            tracker: PitchTracker::new(sample_rate, MIN_FREQUENCY, MAX_FREQUENCY),
+        }
+    }
+
+    pub fn process(&mut self, dtime: f32, x: f32) {
+        // Fast attack and slow release
+        let time_constant = if self.envelope < x.abs() { 0.005 } else { 0.05 };
+        self.envelope += (x.abs() - self.envelope) * (dtime / time_constant).min(1.0);
+
+        self.tracker.process(x);
+    }
+
+    /// Peak level of the input
+    pub fn envelope(&self) -> f32 {
+        self.envelope
+    }
+
+    /// Hz, `None` if the input is silent or unpitched
+    pub fn frequency(&self) -> Option<f32> {
+        self.tracker
+            .pitch()
+            .filter(|pitch| MIN_CONFIDENCE < pitch.confidence && SILENCE < self.envelope)
+            .map(|pitch| pitch.frequency)
+    }
+}
@@ -1,5 +1,10 @@
+pub mod analysis;
 mod benihora_managed;
+mod control_mapping;
+mod follower;
+mod preset;
 mod routine;
+pub mod score;
 pub mod synth;
 pub mod ui;
 mod voice_manager;
@@ -0,0 +1,79 @@
+use crate::benihora_managed::Params as BenihoraParams;
+use crate::routine::Routine;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Preset {
+    pub name: String,
+    pub sound_speed: f32,
+    pub benihora_params: BenihoraParams,
+    pub tongue_poses: Vec<(f32, f32)>,
+    pub other_constrictions: Vec<(f32, f32)>,
+    pub routines: Vec<Routine>,
+    #[serde(default)]
+    pub noteon_routine: usize,
+    #[serde(default)]
+    pub noteoff_routine: usize,
+}
+
+impl Preset {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Self::from_json(&json).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[test]
+fn test_json() {
+    use crate::routine::{Event, Ramp, TimeUnit};
+
+    let mut synth = crate::synth::Synth::new();
+    synth.other_constrictions = vec![(40.0, 0.4)];
+    synth.routines.push(Routine {
+        name: "routine".to_string(),
+        events: vec![
+            (
+                0.0,
+                Event::Constriction {
+                    i: 0,
+                    strength: Some(1.0),
+                    ramp: Some(Ramp {
+                        duration: 0.5,
+                        curve: crate::score::Curve::Smoothstep,
+                    }),
+                },
+            ),
+            (1.0, Event::Routine { index: 0 }),
+        ],
+        time_unit: TimeUnit::Beats,
+    });
+    synth.noteon_routine = 1;
+    let preset = synth.to_preset("preset".to_string());
+
+    let json = preset.to_json();
+    let restored = Preset::from_json(&json).unwrap();
+    assert_eq!(restored.to_json(), json);
+    assert_eq!(restored.name, "preset");
+    assert_eq!(restored.other_constrictions, vec![(40.0, 0.4)]);
+    assert_eq!(restored.routines.last().unwrap().time_unit, TimeUnit::Beats);
+    assert_eq!(restored.noteon_routine, 1);
+
+    // Applying it restores the settings
+    let mut other = crate::synth::Synth::new();
+    other.apply_preset(&restored);
+    assert_eq!(other.to_preset("preset".to_string()).to_json(), json);
+}
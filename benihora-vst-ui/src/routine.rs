@@ -1,12 +1,36 @@
+use crate::score::Curve;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Default, Clone)]
 pub struct Routine {
     pub name: String,
     pub events: Vec<(f32, Event)>,
+    /// Unit of the event delays
+    #[serde(default)]
+    pub time_unit: TimeUnit,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeUnit {
+    #[default]
+    Seconds,
+    /// Quarter notes at the host tempo
+    Beats,
+}
+
+impl TimeUnit {
+    pub const ALL: [TimeUnit; 2] = [TimeUnit::Seconds, TimeUnit::Beats];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimeUnit::Seconds => "Seconds",
+            TimeUnit::Beats => "Beats",
+        }
+    }
 }
 
 impl Routine {
+    /// Interleaves the events of `other`, whose delays are taken in the unit of `self`.
     pub fn merge(&mut self, other: &Self) {
         let mut events = other.events.clone();
         let mut merged = Vec::new();
@@ -36,17 +60,75 @@ pub enum Event {
     Constriction {
         i: usize,
         strength: Option<f32>,
+        #[serde(default)]
+        ramp: Option<Ramp>,
     },
     Velum {
         openness: f32,
+        #[serde(default)]
+        ramp: Option<Ramp>,
     },
     Pitch {
         value: f32,
+        #[serde(default)]
+        ramp: Option<Ramp>,
     },
     Sound {
         sound: bool,
     },
     ForceDiameter,
+    /// Moves the tongue to the coordinates instead of a pose
+    TongueTo {
+        index: f32,
+        diameter: f32,
+        speed: Option<f32>,
+    },
+    /// Moves constriction `i` of the tract until the next `Constriction` event of it
+    ConstrictionPosition {
+        i: usize,
+        position: f32,
+        #[serde(default)]
+        ramp: Option<Ramp>,
+    },
+    Tenseness {
+        value: f32,
+        #[serde(default)]
+        ramp: Option<Ramp>,
+    },
+    Loudness {
+        value: f32,
+        #[serde(default)]
+        ramp: Option<Ramp>,
+    },
+    /// Overrides the intensity of the notes and the controllers until the next note.
+    /// None gives it back to them.
+    Intensity {
+        value: Option<f32>,
+        #[serde(default)]
+        ramp: Option<Ramp>,
+    },
+    /// Overrides `Params::aspiration_level` until the next note. None restores it.
+    Aspiration {
+        level: Option<f32>,
+        #[serde(default)]
+        ramp: Option<Ramp>,
+    },
+    /// Overrides `Params::vibrato_amount` until the next note. None restores it.
+    VibratoDepth {
+        value: Option<f32>,
+        #[serde(default)]
+        ramp: Option<Ramp>,
+    },
+    /// Overrides `Params::vibrato_rate` in Hz until the next note. None restores it.
+    VibratoRate {
+        value: Option<f32>,
+        #[serde(default)]
+        ramp: Option<Ramp>,
+    },
+    /// Plays the routine along with the current one. A routine triggering itself loops.
+    Routine {
+        index: usize,
+    },
 }
 
 impl Event {
@@ -58,6 +140,15 @@ impl Event {
             Event::Pitch { .. } => "Pitch",
             Event::Sound { .. } => "Sound",
             Event::ForceDiameter => "Force Diameter",
+            Event::TongueTo { .. } => "Tongue To",
+            Event::ConstrictionPosition { .. } => "Constriction Position",
+            Event::Tenseness { .. } => "Tenseness",
+            Event::Loudness { .. } => "Loudness",
+            Event::Intensity { .. } => "Intensity",
+            Event::Aspiration { .. } => "Aspiration",
+            Event::VibratoDepth { .. } => "Vibrato Depth",
+            Event::VibratoRate { .. } => "Vibrato Rate",
+            Event::Routine { .. } => "Routine",
         }
     }
 
@@ -69,10 +160,69 @@ impl Event {
             Event::Pitch { .. } => EventKind::Pitch,
             Event::Sound { .. } => EventKind::Sound,
             Event::ForceDiameter => EventKind::ForceDiameter,
+            Event::TongueTo { .. } => EventKind::Tongue,
+            Event::ConstrictionPosition { .. } => EventKind::ConstrictionPosition,
+            Event::Tenseness { .. } => EventKind::Tenseness,
+            Event::Loudness { .. } => EventKind::Loudness,
+            Event::Intensity { .. } => EventKind::Intensity,
+            Event::Aspiration { .. } => EventKind::Aspiration,
+            Event::VibratoDepth { .. } => EventKind::VibratoDepth,
+            Event::VibratoRate { .. } => EventKind::VibratoRate,
+            Event::Routine { .. } => EventKind::Routine,
         }
     }
 }
 
+/// Moves toward the value of the event over `duration` instead of stepping
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Ramp {
+    /// In the time unit of the routine
+    pub duration: f32,
+    #[serde(default)]
+    pub curve: Curve,
+}
+
+impl Event {
+    pub fn ramp(&self) -> Option<Ramp> {
+        match self {
+            Event::Constriction { ramp, .. }
+            | Event::Velum { ramp, .. }
+            | Event::Pitch { ramp, .. }
+            | Event::ConstrictionPosition { ramp, .. }
+            | Event::Tenseness { ramp, .. }
+            | Event::Loudness { ramp, .. }
+            | Event::Intensity { ramp, .. }
+            | Event::Aspiration { ramp, .. }
+            | Event::VibratoDepth { ramp, .. }
+            | Event::VibratoRate { ramp, .. } => *ramp,
+            _ => None,
+        }
+    }
+
+    /// Whether the events change the same thing, so a step cancels the ramp of the other
+    fn same_target(&self, other: &Event) -> bool {
+        match (self, other) {
+            (Event::Constriction { i: a, .. }, Event::Constriction { i: b, .. })
+            | (
+                Event::ConstrictionPosition { i: a, .. },
+                Event::ConstrictionPosition { i: b, .. },
+            ) => a == b,
+            _ => self.kind() == other.kind(),
+        }
+    }
+}
+
+/// The voice that a runtime controls
+pub trait Dispatcher {
+    fn dispatch(&mut self, event: Event);
+    /// Returns the current value of what the ramped `event` changes, or None if it doesn't ramp.
+    fn ramp_start(&mut self, event: &Event) -> Option<f32>;
+    /// Sets what `event` changes to `weight` (0..1) of the way from `start` to the value of `event`.
+    fn ramp(&mut self, event: &Event, start: f32, weight: f32);
+    /// The routine that `Event::Routine` plays
+    fn routine(&mut self, index: usize) -> Option<Routine>;
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum TongueIndex {
     Index(usize),
@@ -87,27 +237,24 @@ pub enum EventKind {
     Pitch,
     Sound,
     ForceDiameter,
+    ConstrictionPosition,
+    Tenseness,
+    Loudness,
+    Intensity,
+    Aspiration,
+    VibratoDepth,
+    VibratoRate,
+    Routine,
 }
 
+/// Events pending in one time unit
 #[derive(Default)]
-pub struct Runtime {
+struct Lane {
     events: Vec<(f32, Event)>,
 }
 
-impl Runtime {
-    pub fn new() -> Self {
-        Self { events: Vec::new() }
-    }
-
-    pub fn push_routine(&mut self, routine: &Routine) {
-        self.push_events(&routine.events);
-    }
-
-    pub fn push_events(&mut self, events: &[(f32, Event)]) {
-        // remove the same type events
-        let mut kinds = events.iter().map(|(_, e)| e.kind()).collect::<Vec<_>>();
-        kinds.sort();
-        kinds.dedup();
+impl Lane {
+    fn remove_kinds(&mut self, kinds: &[EventKind]) {
         let mut i = 0;
         while i < self.events.len() {
             if kinds.contains(&self.events[i].1.kind()) {
@@ -119,7 +266,9 @@ impl Runtime {
                 i += 1;
             }
         }
+    }
 
+    fn merge(&mut self, events: &[(f32, Event)]) {
         let mut events = events.to_vec();
         let mut merged = Vec::new();
 
@@ -139,9 +288,9 @@ impl Runtime {
         self.events = merged;
     }
 
-    pub fn process(&mut self, dtime: f32, mut dispatch: impl FnMut(Event)) {
+    fn process(&mut self, delta: f32, dispatch: &mut impl FnMut(Event)) {
         while !self.events.is_empty() {
-            self.events[0].0 -= dtime;
+            self.events[0].0 -= delta;
             if self.events[0].0 > 0.0 {
                 break;
             }
@@ -150,3 +299,302 @@ impl Runtime {
         }
     }
 }
+
+/// Least delay in the routine's time unit before a sub-routine triggers a routine again,
+/// so a loop without delays plays once per period instead of on every sample
+const MIN_LOOP_PERIOD: f32 = 0.01;
+
+fn delay_subroutines(events: &mut [(f32, Event)]) {
+    let mut time = 0.0;
+    for (delay, event) in events {
+        time += *delay;
+        if matches!(event, Event::Routine { .. }) && time < MIN_LOOP_PERIOD {
+            *delay += MIN_LOOP_PERIOD - time;
+            time = MIN_LOOP_PERIOD;
+        }
+    }
+}
+
+/// An event in the middle of its ramp
+struct Ramping {
+    event: Event,
+    time_unit: TimeUnit,
+    start: f32,
+    elapsed: f32,
+}
+
+#[derive(Default)]
+pub struct Runtime {
+    seconds: Lane,
+    beats: Lane,
+    ramps: Vec<Ramping>,
+    /// Ramps replaced by `push`, which jump to their ends on the next `process`
+    finished: Vec<Event>,
+}
+
+impl Runtime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Plays the routine, replacing the pending events of the same kinds.
+    /// It also stops the loops of the previous routines.
+    pub fn push_routine(&mut self, routine: &Routine) {
+        self.stop_loops();
+        self.push(&routine.events, routine.time_unit);
+    }
+
+    /// Drops the pending `Event::Routine`, which stops the looping routines.
+    pub fn stop_loops(&mut self) {
+        self.seconds.remove_kinds(&[EventKind::Routine]);
+        self.beats.remove_kinds(&[EventKind::Routine]);
+    }
+
+    /// Pushes events whose delays are in seconds.
+    pub fn push_events(&mut self, events: &[(f32, Event)]) {
+        self.push(events, TimeUnit::Seconds);
+    }
+
+    fn push(&mut self, events: &[(f32, Event)], time_unit: TimeUnit) {
+        // remove the same type events
+        let mut kinds = events.iter().map(|(_, e)| e.kind()).collect::<Vec<_>>();
+        kinds.sort();
+        kinds.dedup();
+        self.seconds.remove_kinds(&kinds);
+        self.beats.remove_kinds(&kinds);
+        let finished = &mut self.finished;
+        self.ramps.retain(|r| {
+            let replaced = kinds.contains(&r.event.kind());
+            if replaced {
+                finished.push(r.event.clone());
+            }
+            !replaced
+        });
+
+        match time_unit {
+            TimeUnit::Seconds => self.seconds.merge(events),
+            TimeUnit::Beats => self.beats.merge(events),
+        }
+    }
+
+    /// Advances the time by `dtime` seconds, which are `dbeats` beats at the current tempo.
+    pub fn process(&mut self, dtime: f32, dbeats: f32, dispatcher: &mut impl Dispatcher) {
+        let delta = |time_unit| match time_unit {
+            TimeUnit::Seconds => dtime,
+            TimeUnit::Beats => dbeats,
+        };
+
+        for event in self.finished.drain(..) {
+            dispatcher.dispatch(event);
+        }
+
+        self.ramps.retain_mut(|r| {
+            let ramp = r.event.ramp().unwrap();
+            r.elapsed += delta(r.time_unit);
+            if r.elapsed < ramp.duration {
+                let weight = ramp.curve.ease(r.elapsed / ramp.duration);
+                dispatcher.ramp(&r.event, r.start, weight);
+                true
+            } else {
+                dispatcher.dispatch(r.event.clone());
+                false
+            }
+        });
+
+        let ramps = &mut self.ramps;
+        let mut subroutines = Vec::new();
+        for (lane, time_unit) in [
+            (&mut self.seconds, TimeUnit::Seconds),
+            (&mut self.beats, TimeUnit::Beats),
+        ] {
+            lane.process(delta(time_unit), &mut |event| {
+                if let Event::Routine { index } = event {
+                    subroutines.push(index);
+                    return;
+                }
+                ramps.retain(|r| !r.event.same_target(&event));
+                let start = match event.ramp() {
+                    Some(ramp) if ramp.duration > 0.0 => dispatcher.ramp_start(&event),
+                    _ => None,
+                };
+                if let Some(start) = start {
+                    ramps.push(Ramping {
+                        event,
+                        time_unit,
+                        start,
+                        elapsed: 0.0,
+                    });
+                } else {
+                    dispatcher.dispatch(event);
+                }
+            });
+        }
+
+        // The events of the sub-routines start from the next call
+        for index in subroutines {
+            if let Some(mut routine) = dispatcher.routine(index) {
+                delay_subroutines(&mut routine.events);
+                match routine.time_unit {
+                    TimeUnit::Seconds => self.seconds.merge(&routine.events),
+                    TimeUnit::Beats => self.beats.merge(&routine.events),
+                }
+            }
+        }
+    }
+}
+
+/// Records the dispatched events and the ramps and plays `routines`
+#[cfg(test)]
+#[derive(Default)]
+struct Recorder {
+    routines: Vec<Routine>,
+    dispatched: Vec<Event>,
+    /// Of the ramps in progress
+    weights: Vec<f32>,
+}
+
+#[cfg(test)]
+impl Dispatcher for Recorder {
+    fn dispatch(&mut self, event: Event) {
+        self.dispatched.push(event);
+    }
+
+    fn ramp_start(&mut self, event: &Event) -> Option<f32> {
+        event.ramp().map(|_| 0.0)
+    }
+
+    fn ramp(&mut self, _event: &Event, _start: f32, weight: f32) {
+        self.weights.push(weight);
+    }
+
+    fn routine(&mut self, index: usize) -> Option<Routine> {
+        self.routines.get(index).cloned()
+    }
+}
+
+#[test]
+fn test_loop() {
+    let routine = Routine {
+        name: "loop".to_string(),
+        events: vec![
+            (0.0, Event::Sound { sound: true }),
+            (0.0, Event::Routine { index: 0 }),
+        ],
+        time_unit: TimeUnit::Seconds,
+    };
+    let mut recorder = Recorder {
+        routines: vec![routine.clone()],
+        ..Default::default()
+    };
+    let mut runtime = Runtime::new();
+    runtime.push_routine(&routine);
+
+    // A loop without delays plays once per MIN_LOOP_PERIOD, not on every sample
+    let dtime = 0.001;
+    for _ in 0..100 {
+        runtime.process(dtime, 0.0, &mut recorder);
+    }
+    let count = recorder.dispatched.len();
+    assert!((9..=11).contains(&count), "{count}");
+
+    runtime.stop_loops();
+    recorder.dispatched.clear();
+    for _ in 0..100 {
+        runtime.process(dtime, 0.0, &mut recorder);
+    }
+    assert!(recorder.dispatched.is_empty());
+}
+
+#[test]
+fn test_ramp() {
+    let tenseness = |duration| Event::Tenseness {
+        value: 1.0,
+        ramp: Some(Ramp {
+            duration,
+            curve: Curve::Linear,
+        }),
+    };
+    let mut recorder = Recorder::default();
+    let mut runtime = Runtime::new();
+
+    // It moves linearly and dispatches the event at the end
+    runtime.push_events(&[(0.0, tenseness(0.125))]);
+    for _ in 0..9 {
+        runtime.process(1.0 / 64.0, 0.0, &mut recorder);
+    }
+    assert_eq!(
+        recorder.weights,
+        (1..8).map(|i| i as f32 / 8.0).collect::<Vec<_>>()
+    );
+    assert!(matches!(recorder.dispatched[..], [Event::Tenseness { .. }]));
+
+    // A ramp in beats advances with the beats
+    let mut recorder = Recorder::default();
+    runtime.push_routine(&Routine {
+        name: String::new(),
+        events: vec![(0.0, tenseness(1.0))],
+        time_unit: TimeUnit::Beats,
+    });
+    for _ in 0..9 {
+        runtime.process(1.0, 0.125, &mut recorder);
+    }
+    assert_eq!(recorder.weights.len(), 7);
+    assert_eq!(recorder.dispatched.len(), 1);
+
+    // Pushing the same kind finishes the ramp instead of dropping it
+    let mut recorder = Recorder::default();
+    runtime.push_events(&[(0.0, tenseness(1.0))]);
+    runtime.process(0.25, 0.0, &mut recorder);
+    runtime.push_events(&[(
+        1.0,
+        Event::Tenseness {
+            value: 0.0,
+            ramp: None,
+        },
+    )]);
+    runtime.process(0.25, 0.0, &mut recorder);
+    assert!(recorder.weights.is_empty());
+    assert!(matches!(
+        recorder.dispatched[..],
+        [Event::Tenseness { value, .. }] if value == 1.0
+    ));
+}
+
+#[test]
+fn test_ramp_cancel() {
+    let constriction = |i, ramp: Option<Ramp>| Event::Constriction {
+        i,
+        strength: Some(1.0),
+        ramp,
+    };
+    let ramp = Some(Ramp {
+        duration: 1.0,
+        curve: Curve::Smoothstep,
+    });
+    let mut recorder = Recorder::default();
+    let mut runtime = Runtime::new();
+    runtime.push_events(&[
+        (0.0, constriction(0, ramp)),
+        (0.0, constriction(1, ramp)),
+        (0.5, constriction(0, None)),
+    ]);
+    for _ in 0..20 {
+        runtime.process(0.125, 0.0, &mut recorder);
+    }
+    // The step cancels the ramp of the same constriction but not of the other
+    assert!(matches!(
+        recorder.dispatched[..],
+        [
+            Event::Constriction {
+                i: 0,
+                ramp: None,
+                ..
+            },
+            Event::Constriction {
+                i: 1,
+                ramp: Some(_),
+                ..
+            },
+        ]
+    ));
+}
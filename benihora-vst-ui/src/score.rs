@@ -0,0 +1,416 @@
+//! Keyframed tracks that move the voice continuously,
+//! unlike routines, whose events apply as steps.
+
+use crate::benihora_managed::BenihoraManaged;
+use crate::synth::{Synth, OPEN_CONSTRICTION};
+use benihora::IntervalTimer;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct Score {
+    #[serde(default)]
+    pub name: String,
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Track {
+    pub target: Target,
+    /// Sorted by time
+    pub keyframes: Vec<Keyframe>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    /// Seconds from the start of the score
+    pub time: f32,
+    pub value: f32,
+    /// Interpolation toward the next keyframe
+    #[serde(default)]
+    pub curve: Curve,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Curve {
+    #[default]
+    Linear,
+    /// Eases in and out
+    Smoothstep,
+    /// Easing by the cubic bezier through (0, 0), (x1, y1), (x2, y2), and (1, 1)
+    /// like CSS `cubic-bezier`. x1 and x2 are clamped to 0..1.
+    Bezier { x1: f32, y1: f32, x2: f32, y2: f32 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// In the default tract
+    TongueIndex,
+    TongueDiameter,
+    /// Strength 0..1 of the constriction of `Synth::other_constrictions`.
+    /// 0 releases it and 1 narrows the tract to its diameter.
+    Constriction(usize),
+    /// Openness 0..1
+    Velum,
+    /// Hz
+    Frequency,
+    Tenseness,
+    /// 0..1, the voice is silent at 0
+    Intensity,
+}
+
+impl Curve {
+    /// Maps the progress 0..1 between two keyframes to the interpolation weight.
+    pub fn ease(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        match *self {
+            Curve::Linear => x,
+            Curve::Smoothstep => x * x * (3.0 - 2.0 * x),
+            Curve::Bezier { x1, y1, x2, y2 } => {
+                let bezier = |a: f32, b: f32, t: f32| {
+                    let s = 1.0 - t;
+                    3.0 * s * s * t * a + 3.0 * s * t * t * b + t * t * t
+                };
+                let (x1, x2) = (x1.clamp(0.0, 1.0), x2.clamp(0.0, 1.0));
+                // x(t) is monotonic, so bisect for t
+                let (mut low, mut high) = (0.0, 1.0);
+                for _ in 0..24 {
+                    let t = 0.5 * (low + high);
+                    if bezier(x1, x2, t) < x {
+                        low = t;
+                    } else {
+                        high = t;
+                    }
+                }
+                bezier(y1, y2, 0.5 * (low + high))
+            }
+        }
+    }
+}
+
+impl Track {
+    /// The value at `time`. It holds the first and the last values outside the keyframes.
+    pub fn value(&self, time: f32) -> Option<f32> {
+        let next = self.keyframes.iter().position(|k| time < k.time);
+        match next {
+            Some(0) => self.keyframes.first().map(|k| k.value),
+            Some(i) => {
+                let (a, b) = (&self.keyframes[i - 1], &self.keyframes[i]);
+                let weight = a.curve.ease((time - a.time) / (b.time - a.time));
+                Some(benihora::lerp(a.value, b.value, weight))
+            }
+            None => self.keyframes.last().map(|k| k.value),
+        }
+    }
+}
+
+impl Score {
+    /// Time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.tracks
+            .iter()
+            .filter_map(|t| t.keyframes.last())
+            .map(|k| k.time)
+            .fold(0.0, f32::max)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Parses the score and sorts the keyframes of each track by time.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json).map(Self::sorted)
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, Default::default()).unwrap()
+    }
+
+    /// Same as `from_json` but in RON, which is easier to write by hand.
+    pub fn from_ron(ron: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(ron).map(Self::sorted)
+    }
+
+    fn sorted(mut self) -> Self {
+        for track in &mut self.tracks {
+            track.keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        }
+        self
+    }
+
+    /// Saves in RON if the extension is `.ron`, otherwise in JSON.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let text = if is_ron(path.as_ref()) {
+            self.to_ron()
+        } else {
+            self.to_json()
+        };
+        std::fs::write(path, text)
+    }
+
+    /// Loads RON if the extension is `.ron`, otherwise JSON.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path.as_ref())?;
+        let invalid = |e| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+        if is_ron(path.as_ref()) {
+            Self::from_ron(&text).map_err(|e| invalid(e.to_string()))
+        } else {
+            Self::from_json(&text).map_err(|e| invalid(e.to_string()))
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn is_ron(path: &std::path::Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("ron"))
+}
+
+/// Plays a score on a voice
+pub struct Player {
+    score: Score,
+    time: f32,
+    update_timer: IntervalTimer,
+}
+
+impl Player {
+    pub fn new(score: Score) -> Self {
+        Self {
+            score,
+            time: 0.0,
+            // Faster than the voice's own update rate to keep the glides smooth
+            update_timer: IntervalTimer::new_overflowed(0.005),
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.score.duration() < self.time
+    }
+
+    /// Applies the tracks to `benihora` and advances the time.
+    /// `other_constrictions` are the positions in the default tract and the diameters of `Target::Constriction`.
+    pub fn process(
+        &mut self,
+        dtime: f32,
+        benihora: &mut BenihoraManaged,
+        other_constrictions: &[(f32, f32)],
+    ) {
+        if self.update_timer.overflowed() {
+            for track in &self.score.tracks {
+                let Some(value) = track.value(self.time) else {
+                    continue;
+                };
+                match track.target {
+                    Target::TongueIndex => {
+                        let source = &benihora.benihora.tract.source;
+                        benihora.tract.tongue_target.0 = source.scale_index(value)
+                    }
+                    Target::TongueDiameter => benihora.tract.tongue_target.1 = value,
+                    Target::Constriction(i) => {
+                        let source = &mut benihora.benihora.tract.source;
+                        let scale = source.scale_index(1.0);
+                        if let (Some(&(position, diameter)), Some(constriction)) = (
+                            other_constrictions.get(i),
+                            source.other_constrictions.get_mut(i),
+                        ) {
+                            let position = position * scale;
+                            let diameter =
+                                benihora::lerp(OPEN_CONSTRICTION, diameter, value.clamp(0.0, 1.0));
+                            *constriction = (position, diameter);
+                        }
+                    }
+                    Target::Velum => benihora
+                        .benihora
+                        .tract
+                        .set_velum_target(0.01 + (0.4 - 0.01) * value.clamp(0.0, 1.0)),
+                    Target::Frequency => {
+                        let reset = benihora.get_intensity() < 0.01;
+                        benihora.frequency.set(value, reset);
+                    }
+                    Target::Tenseness => benihora.set_tenseness(value),
+                    Target::Intensity => {
+                        benihora.intensity_target = Some(value.max(0.0));
+                        benihora.sound = value > 0.0;
+                    }
+                }
+            }
+        }
+        self.update_timer.update(dtime);
+        self.time += dtime;
+    }
+
+    /// Gives the voice back to the notes.
+    pub fn release(&self, benihora: &mut BenihoraManaged) {
+        if self
+            .score
+            .tracks
+            .iter()
+            .any(|t| t.target == Target::Intensity)
+        {
+            benihora.intensity_target = None;
+            benihora.sound = false;
+        }
+    }
+}
+
+/// Renders the score with the voice settings of `synth`, followed by the release.
+pub fn render(score: &Score, synth: &Synth, sample_rate: f32) -> Vec<f32> {
+    let mut benihora = BenihoraManaged::new(synth.sound_speed, sample_rate, 1.0, synth.seed);
+    let source = &mut benihora.benihora.tract.source;
+    source.other_constrictions = synth
+        .other_constrictions
+        .iter()
+        .map(|x| (source.scale_index(x.0), OPEN_CONSTRICTION))
+        .collect();
+    let mut player = Player::new(score.clone());
+    let release = 0.3;
+    let length = ((score.duration() + release) * sample_rate) as usize;
+    let dtime = 1.0 / sample_rate;
+    (0..length)
+        .map(|_| {
+            if !player.finished() {
+                player.process(dtime, &mut benihora, &synth.other_constrictions);
+                if player.finished() {
+                    player.release(&mut benihora);
+                }
+            }
+            benihora.process(&synth.benihora_params)
+        })
+        .collect()
+}
+
+#[test]
+fn test_ease() {
+    let curves = [
+        Curve::Linear,
+        Curve::Smoothstep,
+        Curve::Bezier {
+            x1: 0.42,
+            y1: 0.0,
+            x2: 0.58,
+            y2: 1.0,
+        },
+    ];
+    for curve in curves {
+        assert!(curve.ease(0.0).abs() < 1e-4, "{curve:?}");
+        assert!((curve.ease(1.0) - 1.0).abs() < 1e-4, "{curve:?}");
+        // The progress is clamped
+        assert_eq!(curve.ease(-1.0), curve.ease(0.0));
+        assert_eq!(curve.ease(2.0), curve.ease(1.0));
+        let weights: Vec<f32> = (0..=100).map(|i| curve.ease(i as f32 / 100.0)).collect();
+        assert!(weights.windows(2).all(|w| w[0] <= w[1] + 1e-6), "{curve:?}");
+    }
+    assert_eq!(Curve::Linear.ease(0.25), 0.25);
+    assert_eq!(Curve::Smoothstep.ease(0.5), 0.5);
+    assert!(Curve::Smoothstep.ease(0.25) < 0.25);
+
+    // Control points on the diagonal make it linear
+    let bezier = Curve::Bezier {
+        x1: 1.0 / 3.0,
+        y1: 1.0 / 3.0,
+        x2: 2.0 / 3.0,
+        y2: 2.0 / 3.0,
+    };
+    assert!((bezier.ease(0.3) - 0.3).abs() < 1e-4);
+}
+
+#[test]
+fn test_track_value() {
+    let keyframe = |time, value, curve| Keyframe { time, value, curve };
+    let track = Track {
+        target: Target::Frequency,
+        keyframes: vec![
+            keyframe(1.0, 100.0, Curve::Linear),
+            keyframe(2.0, 200.0, Curve::Smoothstep),
+            keyframe(3.0, 100.0, Curve::Linear),
+        ],
+    };
+    // It holds the first and the last values
+    assert_eq!(track.value(0.0), Some(100.0));
+    assert_eq!(track.value(4.0), Some(100.0));
+    // The keyframes are hit exactly
+    assert_eq!(track.value(1.0), Some(100.0));
+    assert_eq!(track.value(2.0), Some(200.0));
+    assert_eq!(track.value(3.0), Some(100.0));
+    assert_eq!(track.value(1.5), Some(150.0));
+    // The curve of a keyframe shapes the way to the next one
+    assert!(track.value(2.25).unwrap() > 175.0);
+    let values: Vec<f32> = (0..=100)
+        .map(|i| track.value(1.0 + i as f32 / 100.0).unwrap())
+        .collect();
+    assert!(values.windows(2).all(|w| w[0] <= w[1]));
+
+    let empty = Track {
+        target: Target::Frequency,
+        keyframes: Vec::new(),
+    };
+    assert_eq!(empty.value(0.0), None);
+}
+
+#[test]
+fn test_from_json() {
+    let json = r#"{"tracks": [{"target": "Velum", "keyframes": [
+        {"time": 1.0, "value": 1.0},
+        {"time": 0.0, "value": 0.0}
+    ]}]}"#;
+    let score = Score::from_json(json).unwrap();
+    let times: Vec<f32> = score.tracks[0].keyframes.iter().map(|k| k.time).collect();
+    assert_eq!(times, vec![0.0, 1.0]);
+    assert_eq!(score.duration(), 1.0);
+    assert_eq!(score.tracks[0].value(0.5), Some(0.5));
+}
+
+#[test]
+fn test_ron() {
+    let ron = r#"(tracks: [(target: Constriction(0), keyframes: [
+        (time: 1.0, value: 1.0, curve: Smoothstep),
+        (time: 0.0, value: 0.0),
+    ])])"#;
+    let score = Score::from_ron(ron).unwrap();
+    assert_eq!(score.tracks[0].target, Target::Constriction(0));
+    assert_eq!(score.tracks[0].keyframes[0].time, 0.0);
+    assert_eq!(score.tracks[0].keyframes[1].curve, Curve::Smoothstep);
+    assert_eq!(
+        Score::from_ron(&score.to_ron()).unwrap().to_json(),
+        score.to_json()
+    );
+}
+
+#[test]
+fn test_constriction_glide() {
+    let mut synth = Synth::new();
+    synth.other_constrictions = vec![(40.0, 0.4)];
+    let score = Score {
+        name: String::new(),
+        tracks: vec![Track {
+            target: Target::Constriction(0),
+            keyframes: vec![
+                Keyframe {
+                    time: 0.0,
+                    value: 0.0,
+                    curve: Curve::Linear,
+                },
+                Keyframe {
+                    time: 0.1,
+                    value: 1.0,
+                    curve: Curve::Linear,
+                },
+            ],
+        }],
+    };
+    let mut benihora = BenihoraManaged::new(synth.sound_speed, 8000.0, 1.0, synth.seed);
+    benihora.benihora.tract.source.other_constrictions = vec![(40.0, OPEN_CONSTRICTION)];
+    let mut player = Player::new(score);
+    let mut diameters = Vec::new();
+    while !player.finished() {
+        player.process(1.0 / 8000.0, &mut benihora, &synth.other_constrictions);
+        diameters.push(benihora.benihora.tract.source.other_constrictions[0].1);
+    }
+    // It narrows without a step from the released diameter
+    assert!(diameters
+        .windows(2)
+        .all(|w| w[1] <= w[0] && w[0] - w[1] < 0.2));
+    assert!(*diameters.last().unwrap() < 0.6);
+}
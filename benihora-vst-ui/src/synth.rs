@@ -1,6 +1,11 @@
-use crate::benihora_managed::{BenihoraManaged, Params as BenihoraParams};
+use crate::benihora_managed::{BenihoraManaged, Params as BenihoraParams, Transport};
+use crate::control_mapping::{default_control_mappings, ControlMapping, Source, Target};
+use crate::follower::Follower;
+use crate::preset::Preset;
 use crate::routine::{self, Routine, Runtime};
+use crate::score::{self, Score};
 use crate::voice_manager::VoiceManager;
+use benihora::Buses;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
@@ -11,12 +16,28 @@ pub struct Synth {
     #[serde(default)]
     pub noteon_sound_delay: f32,
     pub benihora_params: BenihoraParams,
+    /// Indices of the poses and the constrictions are of the default 44-segment tract
+    /// and are scaled to the tract of the voices.
     pub tongue_poses: Vec<(f32, f32)>,
     pub other_constrictions: Vec<(f32, f32)>,
     pub routines: Vec<Routine>,
     pub noteon_routine: usize,
     pub noteoff_routine: usize,
     pub tongue_control: Control,
+    /// Number of voices. 1 is monophonic with last note priority.
+    #[serde(default = "default_polyphony")]
+    pub polyphony: usize,
+    /// Voices share the tract shape of the first voice
+    #[serde(default = "default_shared_tract")]
+    pub shared_tract: bool,
+    #[serde(default = "default_control_mappings")]
+    pub control_mappings: Vec<ControlMapping>,
+    /// Selected by MIDI program change
+    #[serde(default)]
+    pub presets: Vec<Preset>,
+    /// How `process_input` uses the audio input
+    #[serde(default)]
+    pub input_mode: InputMode,
 
     #[serde(skip)]
     pub elapsed_from_note_off: f32,
@@ -26,10 +47,76 @@ pub struct Synth {
     pub voice_manager: VoiceManager,
     #[serde(skip)]
     pub routine_runtime: Runtime,
+    /// Voices other than `benihora` in polyphonic mode
+    #[serde(skip)]
+    pub voices: Vec<Voice>,
     #[serde(skip)]
     reset_required: bool,
     #[serde(skip)]
     random_tongue: u32,
+    /// Active while `input_mode` is `Follower`
+    #[serde(skip)]
+    follower: Option<Follower>,
+    /// Plays a score on the first voice
+    #[serde(skip)]
+    score_player: Option<score::Player>,
+    #[serde(skip)]
+    transport: Transport,
+    /// Smoothed gain of the voice sum
+    #[serde(skip, default = "default_voice_gain")]
+    voice_gain: f32,
+}
+
+fn default_polyphony() -> usize {
+    1
+}
+
+fn default_shared_tract() -> bool {
+    true
+}
+
+fn default_voice_gain() -> f32 {
+    1.0
+}
+
+/// Constrictions wider than this don't narrow the tract. Released constrictions and their ramps end here.
+pub const OPEN_CONSTRICTION: f32 = 3.3;
+
+/// The intensity that the envelope of the follower drives. 40 dB below the full scale is silent.
+fn follower_level(follower: &Follower) -> f32 {
+    (1.0 + 20.0 * follower.envelope().max(1e-6).log10() / 40.0).clamp(0.0, 1.0)
+}
+
+/// Applies the routine events of a voice
+struct VoiceDispatcher<'a> {
+    synth: &'a mut Synth,
+    voice: usize,
+}
+
+impl routine::Dispatcher for VoiceDispatcher<'_> {
+    fn dispatch(&mut self, event: routine::Event) {
+        self.synth.dispatch(self.voice, event);
+    }
+
+    fn ramp_start(&mut self, event: &routine::Event) -> Option<f32> {
+        self.synth.ramp_current(self.voice, event)
+    }
+
+    fn ramp(&mut self, event: &routine::Event, start: f32, weight: f32) {
+        if let Some(end) = self.synth.ramp_end(event) {
+            self.synth
+                .set_ramp_value(self.voice, event, benihora::lerp(start, end, weight));
+        }
+    }
+
+    fn routine(&mut self, index: usize) -> Option<Routine> {
+        self.synth.routines.get(index).cloned()
+    }
+}
+
+pub struct Voice {
+    pub benihora: BenihoraManaged,
+    pub routine_runtime: Runtime,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -38,11 +125,56 @@ pub enum Control {
     Internal,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputMode {
+    /// The input is ignored
+    #[default]
+    Off,
+    /// The input excites the tract of the first voice instead of the glottis.
+    /// The other voices are not played, so it is monophonic whatever the polyphony.
+    Talkbox,
+    /// The pitch and the envelope of the input drive the first voice
+    Follower,
+}
+
+impl InputMode {
+    pub const ALL: [InputMode; 3] = [InputMode::Off, InputMode::Talkbox, InputMode::Follower];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputMode::Off => "Off",
+            InputMode::Talkbox => "Talkbox",
+            InputMode::Follower => "Follower",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Event {
-    NoteOn { note: u8, velocity: f32 },
-    NoteOff { note: u8 },
-    PitchBend { value: f32 },
+    NoteOn {
+        note: u8,
+        velocity: f32,
+    },
+    NoteOff {
+        note: u8,
+    },
+    PitchBend {
+        value: f32,
+    },
+    /// value: 0.0 - 1.0
+    ControlChange {
+        cc: u8,
+        value: f32,
+    },
+    /// pressure: 0.0 - 1.0
+    ChannelPressure {
+        pressure: f32,
+    },
+    /// pressure: 0.0 - 1.0
+    PolyPressure {
+        note: u8,
+        pressure: f32,
+    },
 }
 
 impl Synth {
@@ -79,6 +211,7 @@ impl Synth {
                             },
                         ),
                     ],
+                    time_unit: routine::TimeUnit::Seconds,
                 },
                 Routine {
                     name: "Tap".to_string(),
@@ -89,6 +222,7 @@ impl Synth {
                             routine::Event::Constriction {
                                 i: 1,
                                 strength: Some(0.7),
+                                ramp: None,
                             },
                         ),
                         (0.0, routine::Event::ForceDiameter),
@@ -97,10 +231,12 @@ impl Synth {
                             routine::Event::Constriction {
                                 i: 1,
                                 strength: None,
+                                ramp: None,
                             },
                         ),
                         (0.01, routine::Event::Sound { sound: true }),
                     ],
+                    time_unit: routine::TimeUnit::Seconds,
                 },
             ],
             noteon_routine: 0,
@@ -110,75 +246,489 @@ impl Synth {
             voice_manager: VoiceManager::new(),
             routine_runtime: Runtime::new(),
             tongue_control: Control::Internal,
+            polyphony: 1,
+            shared_tract: true,
+            control_mappings: default_control_mappings(),
+            presets: Vec::new(),
+            input_mode: InputMode::Off,
+            voices: Vec::new(),
             reset_required: true,
             random_tongue: 1,
+            follower: None,
+            score_player: None,
+            transport: Transport::default(),
+            voice_gain: default_voice_gain(),
         }
     }
 
+    /// Follows the host. `tempo` is in BPM and `position` in quarter notes, None while stopped.
+    /// Between the calls the position advances at the tempo.
+    pub fn set_transport(&mut self, tempo: f32, position: Option<f64>) {
+        self.transport = Transport {
+            tempo: tempo.max(1.0),
+            position,
+        };
+    }
+
     pub fn trigger_routine(&mut self, index: usize) {
+        for voice in 0..self.voice_count() {
+            self.trigger_voice_routine(voice, index);
+        }
+    }
+
+    fn trigger_voice_routine(&mut self, voice: usize, index: usize) {
         if self.routines.len() <= index {
             return;
         }
-        self.routine_runtime.push_routine(&self.routines[index]);
+        let routine = &self.routines[index];
+        if voice == 0 {
+            self.routine_runtime.push_routine(routine);
+        } else {
+            self.voices[voice - 1].routine_runtime.push_routine(routine);
+        }
+    }
+
+    /// Plays the score on the first voice from the start, replacing the playing one.
+    pub fn play_score(&mut self, score: Score) {
+        self.stop_score();
+        self.score_player = Some(score::Player::new(score));
+    }
+
+    pub fn stop_score(&mut self) {
+        if let (Some(player), Some(main)) = (self.score_player.take(), self.benihora.as_mut()) {
+            player.release(main);
+        }
+    }
+
+    pub fn is_score_playing(&self) -> bool {
+        self.score_player.is_some()
+    }
+
+    pub fn voice_count(&self) -> usize {
+        1 + self.voices.len()
+    }
+
+    pub fn voice_mut(&mut self, voice: usize) -> &mut BenihoraManaged {
+        if voice == 0 {
+            self.benihora.as_mut().unwrap()
+        } else {
+            &mut self.voices[voice - 1].benihora
+        }
+    }
+
+    /// Maps an index of `tongue_poses` and `other_constrictions` to the tract of the voices.
+    fn tract_index(&self, index: f32) -> f32 {
+        self.voice(0).benihora.tract.source.scale_index(index)
+    }
+
+    fn runtime_mut(&mut self, voice: usize) -> &mut Runtime {
+        if voice == 0 {
+            &mut self.routine_runtime
+        } else {
+            &mut self.voices[voice - 1].routine_runtime
+        }
     }
 
     pub fn process(&mut self, dtime: f32) -> f32 {
-        let benihora = self.benihora.as_mut().unwrap();
-        self.routine_runtime.process(dtime, |e| match e {
+        self.update(dtime);
+
+        let gain = self.voice_gain(dtime);
+        let main = self.benihora.as_mut().unwrap();
+        let mut y = main.process(&self.benihora_params);
+        for voice in &mut self.voices {
+            voice.benihora.intensity_pid_enabled = main.intensity_pid_enabled;
+            y += voice.benihora.process(&self.benihora_params);
+        }
+        y * gain
+    }
+
+    /// Same as `process` but returns the mouth, nose, and glottal source signals separately.
+    pub fn process_buses(&mut self, dtime: f32) -> Buses {
+        self.update(dtime);
+
+        let gain = self.voice_gain(dtime);
+        let main = self.benihora.as_mut().unwrap();
+        let mut y = main.process_buses(&self.benihora_params);
+        for voice in &mut self.voices {
+            voice.benihora.intensity_pid_enabled = main.intensity_pid_enabled;
+            y = y + voice.benihora.process_buses(&self.benihora_params);
+        }
+        y * gain
+    }
+
+    /// The voices are uncorrelated and add up in power, so their sum is scaled by 1/sqrt(N)
+    /// of the N sounding voices to keep a chord about as loud as a single note.
+    /// It glides so that notes starting and ending don't step the level.
+    fn voice_gain(&mut self, dtime: f32) -> f32 {
+        let sounding = (0..self.voice_count())
+            .filter(|&voice| self.voice(voice).get_intensity() > 0.01)
+            .count()
+            .max(1);
+        let target = 1.0 / (sounding as f32).sqrt();
+        self.voice_gain += (target - self.voice_gain) * (dtime / 0.05).min(1.0);
+        self.voice_gain
+    }
+
+    /// Same as `process` but uses `input` according to `input_mode`.
+    pub fn process_input(&mut self, dtime: f32, input: f32) -> f32 {
+        self.release_follower();
+        match self.input_mode {
+            InputMode::Off => self.process(dtime),
+            InputMode::Talkbox => {
+                self.update(dtime);
+                let main = self.benihora.as_mut().unwrap();
+                main.process_excitation(&self.benihora_params, input)
+            }
+            InputMode::Follower => {
+                self.follow(dtime, input);
+                self.process(dtime)
+            }
+        }
+    }
+
+    /// Same as `process_input` but returns the buses.
+    pub fn process_buses_input(&mut self, dtime: f32, input: f32) -> Buses {
+        self.release_follower();
+        match self.input_mode {
+            InputMode::Off => self.process_buses(dtime),
+            InputMode::Talkbox => {
+                self.update(dtime);
+                let main = self.benihora.as_mut().unwrap();
+                main.process_excitation_buses(&self.benihora_params, input)
+            }
+            InputMode::Follower => {
+                self.follow(dtime, input);
+                self.process_buses(dtime)
+            }
+        }
+    }
+
+    fn follow(&mut self, dtime: f32, input: f32) {
+        let follower = self
+            .follower
+            .get_or_insert_with(|| Follower::new(1.0 / dtime));
+        let pitched = follower.frequency().is_some();
+        follower.process(dtime, input);
+
+        let main = self.benihora.as_mut().unwrap();
+        main.intensity_target = Some(follower_level(follower));
+        main.sound = follower.frequency().is_some();
+        if let Some(frequency) = follower.frequency() {
+            main.frequency.set(frequency, !pitched);
+        }
+    }
+
+    /// Gives the control back to the notes when the follower is turned off.
+    /// What the notes and the controllers have set since the last sample of the follower is kept.
+    fn release_follower(&mut self) {
+        if self.input_mode == InputMode::Follower {
+            return;
+        }
+        let Some(follower) = self.follower.take() else {
+            return;
+        };
+        let held = if self.polyphony > 1 {
+            self.voice_manager.slot(0).and_then(|s| s.note).is_some()
+        } else {
+            self.voice_manager.get_voice().is_some()
+        };
+        let main = self.benihora.as_mut().unwrap();
+        if main.intensity_target == Some(follower_level(&follower)) {
+            main.intensity_target = None;
+        }
+        if follower.frequency().is_some() && !held {
+            main.sound = false;
+        }
+    }
+
+    fn update(&mut self, dtime: f32) {
+        let transport = self.transport;
+        let dbeats = self.transport.advance(dtime);
+        for voice in 0..self.voice_count() {
+            self.voice_mut(voice).transport = transport;
+            let mut runtime = std::mem::take(self.runtime_mut(voice));
+            runtime.process(dtime, dbeats, &mut VoiceDispatcher { synth: self, voice });
+            *self.runtime_mut(voice) = runtime;
+        }
+
+        if let Some(player) = &mut self.score_player {
+            player.process(
+                dtime,
+                self.benihora.as_mut().unwrap(),
+                &self.other_constrictions,
+            );
+            if player.finished() {
+                self.stop_score();
+            }
+        }
+
+        if self.shared_tract {
+            self.sync_tracts();
+        }
+
+        self.elapsed_from_note_off += dtime;
+    }
+
+    /// Applies a routine event of the voice.
+    /// With the shared tract, tract events are applied to the first voice and copied to the others.
+    fn dispatch(&mut self, voice: usize, event: routine::Event) {
+        let tract_voice = if self.shared_tract { 0 } else { voice };
+        match event {
             routine::Event::Tongue { index, speed } => {
-                match index {
+                let tongue = match index {
                     routine::TongueIndex::Index(i) => {
                         if self.tongue_poses.len() <= i {
                             return;
                         }
-                        benihora.tract.tongue_target = self.tongue_poses[i];
+                        self.tongue_poses[i]
                     }
                     routine::TongueIndex::Random => {
                         let seed = &mut self.random_tongue;
                         *seed = seed.overflowing_mul(48271).0 % ((1 << 31) - 1);
 
-                        benihora.tract.tongue_target =
-                            self.tongue_poses[*seed as usize % self.tongue_poses.len()];
+                        self.tongue_poses[*seed as usize % self.tongue_poses.len()]
                     }
-                }
+                };
+                let tongue = (self.tract_index(tongue.0), tongue.1);
+                let benihora = self.voice_mut(tract_voice);
+                benihora.tract.tongue_target = tongue;
                 if let Some(speed) = speed {
                     benihora.tract.speed = speed;
                 }
             }
-            routine::Event::Constriction { i, strength } => {
+            routine::Event::Constriction { i, strength, .. } => {
                 if self.other_constrictions.len() <= i {
                     return;
                 }
                 let diameter = if let Some(strength) = strength {
                     self.other_constrictions[i].1 * (1.0 - strength)
                 } else {
-                    10.0
+                    OPEN_CONSTRICTION
                 };
-                benihora.benihora.tract.source.other_constrictions[i] =
-                    (self.other_constrictions[i].0, diameter);
+                let position = self.tract_index(self.other_constrictions[i].0);
+                self.voice_mut(tract_voice)
+                    .benihora
+                    .tract
+                    .source
+                    .other_constrictions[i] = (position, diameter);
             }
-            routine::Event::Velum { openness } => {
-                benihora
+            routine::Event::Velum { openness, .. } => {
+                self.voice_mut(tract_voice)
                     .benihora
                     .tract
                     .set_velum_target(0.01 + (0.4 - 0.01) * openness);
             }
-            routine::Event::Pitch { value } => {
-                benihora.frequency.pitchbend = 2.0f32.powf((value as f32 * 2.0 - 1.0) / 12.0);
+            routine::Event::Pitch { value, .. } => {
+                self.voice_mut(voice).frequency.pitchbend =
+                    2.0f32.powf((value as f32 * 2.0 - 1.0) / 12.0);
             }
             routine::Event::Sound { sound } => {
-                benihora.sound = sound;
+                self.voice_mut(voice).sound = sound;
             }
             routine::Event::ForceDiameter => {
-                benihora.benihora.tract.update_diameter();
-                benihora.benihora.tract.current_diameter =
-                    benihora.benihora.tract.target_diameter.clone();
+                let voices = if self.shared_tract {
+                    self.sync_tracts();
+                    0..self.voice_count()
+                } else {
+                    voice..voice + 1
+                };
+                for voice in voices {
+                    let tract = &mut self.voice_mut(voice).benihora.tract;
+                    tract.update_diameter();
+                    tract.current_diameter = tract.target_diameter.clone();
+                }
+            }
+            routine::Event::TongueTo {
+                index,
+                diameter,
+                speed,
+            } => {
+                let index = self.tract_index(index);
+                let benihora = self.voice_mut(tract_voice);
+                benihora.tract.tongue_target =
+                    benihora.benihora.tract.source.tongue_clamp(index, diameter);
+                if let Some(speed) = speed {
+                    benihora.tract.speed = speed;
+                }
+            }
+            routine::Event::ConstrictionPosition { i, position, .. } => {
+                let position = self.tract_index(position);
+                let tract = &mut self.voice_mut(tract_voice).benihora.tract;
+                if let Some(constriction) = tract.source.other_constrictions.get_mut(i) {
+                    constriction.0 = position;
+                }
+            }
+            routine::Event::Tenseness { value, .. } => {
+                self.voice_mut(voice).set_tenseness(value);
+            }
+            routine::Event::Loudness { value, .. } => {
+                self.voice_mut(voice).loudness.target = value.clamp(0.0, 1.0);
+            }
+            routine::Event::Intensity { value, .. } => {
+                self.voice_mut(voice).routine_intensity = value.map(|v| v.max(0.0));
             }
-        });
+            routine::Event::Aspiration { level, .. } => {
+                self.voice_mut(voice).aspiration_level = level;
+            }
+            routine::Event::VibratoDepth { value, .. } => {
+                self.voice_mut(voice).vibrato_amount = value;
+            }
+            routine::Event::VibratoRate { value, .. } => {
+                self.voice_mut(voice).vibrato_rate = value;
+            }
+            // Played by the runtime
+            routine::Event::Routine { .. } => {}
+        }
+    }
 
-        self.elapsed_from_note_off += dtime;
+    /// The value that a ramp of the event moves now, in the space where it is interpolated
+    fn ramp_current(&self, voice: usize, event: &routine::Event) -> Option<f32> {
+        let tract_voice = if self.shared_tract { 0 } else { voice };
+        let tract = &self.voice(tract_voice).benihora.tract;
+        match *event {
+            routine::Event::Constriction { i, .. } => {
+                self.other_constrictions.get(i)?;
+                let diameter = tract.source.other_constrictions.get(i)?.1;
+                Some(diameter.min(OPEN_CONSTRICTION))
+            }
+            routine::Event::Velum { .. } => Some(tract.velum_target()),
+            // Interpolate the pitch in the log scale
+            routine::Event::Pitch { .. } => Some(self.voice(voice).frequency.pitchbend.ln()),
+            routine::Event::ConstrictionPosition { i, .. } => Some(
+                tract
+                    .source
+                    .unscale_index(tract.source.other_constrictions.get(i)?.0),
+            ),
+            routine::Event::Tenseness { .. } => Some(self.voice(voice).tenseness.target_tenseness),
+            routine::Event::Loudness { .. } => Some(self.voice(voice).loudness.target),
+            routine::Event::Intensity { .. } => {
+                let benihora = self.voice(voice);
+                Some(
+                    benihora
+                        .routine_intensity
+                        .or(benihora.intensity_target)
+                        .unwrap_or(self.benihora_params.noteon_intensity),
+                )
+            }
+            routine::Event::Aspiration { .. } => Some(
+                self.voice(voice)
+                    .aspiration_level
+                    .unwrap_or(self.benihora_params.aspiration_level),
+            ),
+            routine::Event::VibratoDepth { .. } => Some(
+                self.voice(voice)
+                    .vibrato_amount
+                    .unwrap_or(self.benihora_params.vibrato_amount),
+            ),
+            routine::Event::VibratoRate { .. } => Some(
+                self.voice(voice)
+                    .vibrato_rate
+                    .unwrap_or(self.benihora_params.vibrato_rate),
+            ),
+            _ => None,
+        }
+    }
+
+    /// The value where a ramp of the event ends, in the same space as `ramp_current`
+    fn ramp_end(&self, event: &routine::Event) -> Option<f32> {
+        match *event {
+            routine::Event::Constriction { i, strength, .. } => {
+                let diameter = self.other_constrictions.get(i)?.1;
+                Some(strength.map_or(OPEN_CONSTRICTION, |s| diameter * (1.0 - s)))
+            }
+            routine::Event::Velum { openness, .. } => Some(0.01 + (0.4 - 0.01) * openness),
+            routine::Event::Pitch { value, .. } => {
+                Some((value * 2.0 - 1.0) / 12.0 * std::f32::consts::LN_2)
+            }
+            routine::Event::ConstrictionPosition { position, .. } => Some(position),
+            routine::Event::Tenseness { value, .. } | routine::Event::Loudness { value, .. } => {
+                Some(value)
+            }
+            routine::Event::Intensity { value, .. } => {
+                Some(value.unwrap_or(self.benihora_params.noteon_intensity))
+            }
+            routine::Event::Aspiration { level, .. } => {
+                Some(level.unwrap_or(self.benihora_params.aspiration_level))
+            }
+            routine::Event::VibratoDepth { value, .. } => {
+                Some(value.unwrap_or(self.benihora_params.vibrato_amount))
+            }
+            routine::Event::VibratoRate { value, .. } => {
+                Some(value.unwrap_or(self.benihora_params.vibrato_rate))
+            }
+            _ => None,
+        }
+    }
+
+    fn set_ramp_value(&mut self, voice: usize, event: &routine::Event, value: f32) {
+        let tract_voice = if self.shared_tract { 0 } else { voice };
+        match *event {
+            routine::Event::Constriction { i, .. } => {
+                let tract = &mut self.voice_mut(tract_voice).benihora.tract;
+                if let Some(constriction) = tract.source.other_constrictions.get_mut(i) {
+                    constriction.1 = value;
+                }
+            }
+            routine::Event::Velum { .. } => {
+                self.voice_mut(tract_voice)
+                    .benihora
+                    .tract
+                    .set_velum_target(value);
+            }
+            routine::Event::Pitch { .. } => {
+                self.voice_mut(voice).frequency.pitchbend = value.exp();
+            }
+            routine::Event::ConstrictionPosition { i, .. } => {
+                let position = self.tract_index(value);
+                let tract = &mut self.voice_mut(tract_voice).benihora.tract;
+                if let Some(constriction) = tract.source.other_constrictions.get_mut(i) {
+                    constriction.0 = position;
+                }
+            }
+            routine::Event::Tenseness { .. } => self.voice_mut(voice).set_tenseness(value),
+            routine::Event::Loudness { .. } => self.voice_mut(voice).loudness.target = value,
+            routine::Event::Intensity { .. } => {
+                self.voice_mut(voice).routine_intensity = Some(value.max(0.0))
+            }
+            routine::Event::Aspiration { .. } => {
+                self.voice_mut(voice).aspiration_level = Some(value)
+            }
+            routine::Event::VibratoDepth { .. } => {
+                self.voice_mut(voice).vibrato_amount = Some(value)
+            }
+            routine::Event::VibratoRate { .. } => self.voice_mut(voice).vibrato_rate = Some(value),
+            _ => {}
+        }
+    }
 
-        benihora.process(&self.benihora_params)
+    fn voice(&self, voice: usize) -> &BenihoraManaged {
+        if voice == 0 {
+            self.benihora.as_ref().unwrap()
+        } else {
+            &self.voices[voice - 1].benihora
+        }
+    }
+
+    /// Copies the tract targets of the first voice to the other voices.
+    fn sync_tracts(&mut self) {
+        let Some(main) = self.benihora.as_ref() else {
+            return;
+        };
+        for voice in &mut self.voices {
+            let benihora = &mut voice.benihora;
+            benihora.tract.tongue_target = main.tract.tongue_target;
+            benihora.tract.speed = main.tract.speed;
+            benihora
+                .benihora
+                .tract
+                .source
+                .other_constrictions
+                .clone_from(&main.benihora.tract.source.other_constrictions);
+            benihora
+                .benihora
+                .tract
+                .set_velum_target(main.benihora.tract.velum_target());
+        }
     }
 
     pub fn handle_event(&mut self, event: &Event) {
@@ -186,28 +736,36 @@ impl Synth {
 
         match event {
             Event::NoteOn { note, velocity } => {
-                let benihora = self.benihora.as_mut().unwrap();
                 if (base..base + self.tongue_poses.len() as u8).contains(note) {
                     let (index, diameter) = self.tongue_poses[*note as usize - base as usize];
-                    benihora.tract.tongue_target =
-                        benihora.benihora.tract.source.tongue_clamp(index, diameter);
+                    let index = self.tract_index(index);
+                    for voice in 0..self.voice_count() {
+                        let benihora = self.voice_mut(voice);
+                        benihora.tract.tongue_target =
+                            benihora.benihora.tract.source.tongue_clamp(index, diameter);
+                    }
                     return;
                 }
                 let base = base + self.tongue_poses.len() as u8;
                 if (base..base + self.other_constrictions.len() as u8).contains(note) {
                     let i = *note as usize - base as usize;
                     let diameter = self.other_constrictions[i].1 * (1.0 - *velocity as f32);
-                    benihora.benihora.tract.source.other_constrictions[i] =
-                        (self.other_constrictions[i].0, diameter);
-                    benihora.benihora.tract.update_diameter();
+                    let position = self.tract_index(self.other_constrictions[i].0);
+                    for voice in 0..self.voice_count() {
+                        let tract = &mut self.voice_mut(voice).benihora.tract;
+                        tract.source.other_constrictions[i] = (position, diameter);
+                        tract.update_diameter();
+                    }
                     return;
                 }
                 let base = base + self.other_constrictions.len() as u8;
                 if *note == base {
-                    benihora
-                        .benihora
-                        .tract
-                        .set_velum_target(0.01 + (0.4 - 0.01) * *velocity as f32);
+                    for voice in 0..self.voice_count() {
+                        self.voice_mut(voice)
+                            .benihora
+                            .tract
+                            .set_velum_target(0.01 + (0.4 - 0.01) * *velocity as f32);
+                    }
                     return;
                 }
                 let base = base + 1;
@@ -216,11 +774,35 @@ impl Synth {
                     return;
                 }
 
+                if self.polyphony > 1 {
+                    let voice = self
+                        .voice_manager
+                        .allocate(*note, *velocity, self.voice_count());
+                    let benihora = self.voice_mut(voice);
+                    let muted = benihora.get_intensity() < 0.01;
+                    benihora.reset_overrides();
+                    benihora
+                        .frequency
+                        .set(440.0 * 2.0f32.powf((*note as f32 - 69.0) / 12.0), muted);
+                    benihora.set_tenseness(*velocity);
+                    let delay = self.noteon_sound_delay;
+                    // The slot may be stolen from a note whose routine is still playing
+                    *self.runtime_mut(voice) = Runtime::new();
+                    self.runtime_mut(voice)
+                        .push_events(&[(delay, routine::Event::Sound { sound: true })]);
+                    if (1..=self.routines.len()).contains(&self.noteon_routine) {
+                        self.trigger_voice_routine(voice, self.noteon_routine - 1);
+                    }
+                    return;
+                }
+
+                let benihora = self.benihora.as_mut().unwrap();
                 let frequency_reset_time = 0.25;
                 let muted = benihora.get_intensity() < 0.01
                     && frequency_reset_time - self.elapsed_from_note_off < 0.0;
                 self.voice_manager.noteon(*note);
                 if let Some(note) = self.voice_manager.get_voice() {
+                    benihora.reset_overrides();
                     benihora
                         .frequency
                         .set(440.0 * 2.0f32.powf((note as f32 - 69.0) / 12.0), muted);
@@ -236,18 +818,22 @@ impl Synth {
                 }
             }
             Event::NoteOff { note } => {
-                let benihora = self.benihora.as_mut().unwrap();
                 let base = base + self.tongue_poses.len() as u8;
                 if (base..base + self.other_constrictions.len() as u8).contains(note) {
                     let i = *note as usize - base as usize;
-                    benihora.benihora.tract.source.other_constrictions[i] =
-                        (self.other_constrictions[i].0, 10.0);
-                    benihora.benihora.tract.update_diameter();
+                    let position = self.tract_index(self.other_constrictions[i].0);
+                    for voice in 0..self.voice_count() {
+                        let tract = &mut self.voice_mut(voice).benihora.tract;
+                        tract.source.other_constrictions[i] = (position, OPEN_CONSTRICTION);
+                        tract.update_diameter();
+                    }
                     return;
                 }
                 let base = base + self.other_constrictions.len() as u8;
                 if *note == base {
-                    benihora.benihora.tract.set_velum_target(0.01);
+                    for voice in 0..self.voice_count() {
+                        self.voice_mut(voice).benihora.tract.set_velum_target(0.01);
+                    }
                     return;
                 }
                 let base = base + 1;
@@ -255,6 +841,20 @@ impl Synth {
                     return;
                 }
 
+                if self.polyphony > 1 {
+                    if let Some(voice) = self.voice_manager.release(*note) {
+                        let runtime = self.runtime_mut(voice);
+                        runtime.stop_loops();
+                        runtime.push_events(&[(0.0, routine::Event::Sound { sound: false })]);
+                        if (1..=self.routines.len()).contains(&self.noteoff_routine) {
+                            self.trigger_voice_routine(voice, self.noteoff_routine - 1);
+                        }
+                    }
+                    self.elapsed_from_note_off = 0.0;
+                    return;
+                }
+
+                let benihora = self.benihora.as_mut().unwrap();
                 self.voice_manager.noteoff(*note);
                 if let Some(note) = self.voice_manager.get_voice() {
                     benihora
@@ -263,6 +863,7 @@ impl Synth {
                     benihora.sound = true;
                 } else {
                     // benihora.sound = false;
+                    self.routine_runtime.stop_loops();
                     self.routine_runtime
                         .push_events(&[(0.0, routine::Event::Sound { sound: false })]);
                     self.elapsed_from_note_off = 0.0;
@@ -272,11 +873,149 @@ impl Synth {
                 }
             }
             Event::PitchBend { value } => {
-                self.benihora.as_mut().unwrap().frequency.pitchbend = 2.0f32.powf(*value);
+                for voice in 0..self.voice_count() {
+                    self.voice_mut(voice).frequency.pitchbend = 2.0f32.powf(*value);
+                }
+            }
+            Event::ControlChange { cc, value } => {
+                self.apply_control(Source::ControlChange(*cc), *value, None);
+            }
+            Event::ChannelPressure { pressure } => {
+                self.apply_control(Source::ChannelPressure, *pressure, None);
+            }
+            Event::PolyPressure { note, pressure } => {
+                let voice = if self.polyphony > 1 {
+                    self.voice_manager.find(*note)
+                } else {
+                    (self.voice_manager.get_voice() == Some(*note)).then_some(0)
+                };
+                if let Some(voice) = voice {
+                    self.apply_control(Source::PolyPressure, *pressure, Some(voice));
+                }
+            }
+        }
+    }
+
+    /// Applies the mapped controller value to the voice, or to all voices if `voice` is None.
+    fn apply_control(&mut self, source: Source, value: f32, voice: Option<usize>) {
+        let voices = match voice {
+            Some(voice) => voice..voice + 1,
+            None => 0..self.voice_count(),
+        };
+
+        for i in 0..self.control_mappings.len() {
+            let mapping = &self.control_mappings[i];
+            if mapping.source != source {
+                continue;
+            }
+            let target = mapping.target;
+            let value = mapping.map(value);
+            let constriction = match target {
+                Target::Constriction(i) => {
+                    let Some(&(position, diameter)) = self.other_constrictions.get(i) else {
+                        continue;
+                    };
+                    let diameter = if value > 0.0 {
+                        diameter * (1.0 - value.min(1.0))
+                    } else {
+                        OPEN_CONSTRICTION
+                    };
+                    (self.tract_index(position), diameter)
+                }
+                _ => (0.0, 0.0),
+            };
+            // The range of the tongue x is in the default tract
+            let tongue_index = self.tract_index(value);
+
+            for voice in voices.clone() {
+                let benihora = self.voice_mut(voice);
+                let (index, diameter) = benihora.tract.tongue_target;
+                let source = &benihora.benihora.tract.source;
+                match target {
+                    Target::Tenseness => benihora.set_tenseness(value),
+                    Target::Intensity => benihora.intensity_target = Some(value.max(0.0)),
+                    Target::TongueX => {
+                        benihora.tract.tongue_target = source.tongue_clamp(tongue_index, diameter)
+                    }
+                    Target::TongueY => {
+                        benihora.tract.tongue_target = source.tongue_clamp(index, value)
+                    }
+                    Target::Velum => benihora
+                        .benihora
+                        .tract
+                        .set_velum_target(0.01 + (0.4 - 0.01) * value.clamp(0.0, 1.0)),
+                    Target::Constriction(i) => {
+                        let tract = &mut benihora.benihora.tract;
+                        tract.source.other_constrictions[i] = constriction;
+                        tract.update_diameter();
+                    }
+                }
             }
         }
     }
 
+    /// Gives the intensity back to the notes unless a controller is still mapped to it.
+    /// Call it after removing or retargeting a control mapping.
+    pub fn release_control_intensity(&mut self) {
+        if self
+            .control_mappings
+            .iter()
+            .any(|m| m.target == Target::Intensity)
+        {
+            return;
+        }
+        for voice in 0..self.voice_count() {
+            self.voice_mut(voice).intensity_target = None;
+        }
+    }
+
+    pub fn to_preset(&self, name: String) -> Preset {
+        Preset {
+            name,
+            sound_speed: self.sound_speed,
+            benihora_params: self.benihora_params.clone(),
+            tongue_poses: self.tongue_poses.clone(),
+            other_constrictions: self.other_constrictions.clone(),
+            routines: self.routines.clone(),
+            noteon_routine: self.noteon_routine,
+            noteoff_routine: self.noteoff_routine,
+        }
+    }
+
+    pub fn apply_preset(&mut self, preset: &Preset) {
+        if self.sound_speed != preset.sound_speed {
+            self.sound_speed = preset.sound_speed;
+            self.request_reset();
+        }
+        self.benihora_params = preset.benihora_params.clone();
+        self.tongue_poses = preset.tongue_poses.clone();
+        self.other_constrictions = preset.other_constrictions.clone();
+        self.routines = preset.routines.clone();
+        self.noteon_routine = preset.noteon_routine;
+        self.noteoff_routine = preset.noteoff_routine;
+
+        if self.benihora.is_some() {
+            for voice in 0..self.voice_count() {
+                let other_constrictions = self
+                    .other_constrictions
+                    .iter()
+                    .map(|x| (self.tract_index(x.0), OPEN_CONSTRICTION))
+                    .collect();
+                let tract = &mut self.voice_mut(voice).benihora.tract;
+                tract.source.other_constrictions = other_constrictions;
+                tract.update_diameter();
+            }
+        }
+    }
+
+    /// Applies the preset of the bank. Does nothing if the index is out of range.
+    /// It allocates, so hosts call it off the audio thread on program changes.
+    pub fn load_preset(&mut self, index: usize) {
+        if let Some(preset) = self.presets.get(index).cloned() {
+            self.apply_preset(&preset);
+        }
+    }
+
     pub fn ensure_benihora(&mut self, sample_rate: f32) {
         if self.benihora.is_none() || self.reset_required {
             self.benihora = Some(BenihoraManaged::new(
@@ -285,6 +1024,18 @@ impl Synth {
                 1.0,
                 self.seed,
             ));
+            self.voices = (1..self.polyphony.max(1))
+                .map(|i| Voice {
+                    benihora: BenihoraManaged::new(
+                        self.sound_speed,
+                        sample_rate,
+                        1.0,
+                        self.seed + i as u32 * 16,
+                    ),
+                    routine_runtime: Runtime::new(),
+                })
+                .collect();
+            self.voice_manager = VoiceManager::new();
             self.ensure_other_constriction();
             self.random_tongue = self.seed + 1;
             self.reset_required = false;
@@ -292,19 +1043,22 @@ impl Synth {
     }
 
     pub fn ensure_other_constriction(&mut self) {
-        let benihora = self.benihora.as_mut().unwrap();
-        if benihora
-            .benihora
-            .tract
-            .source
-            .other_constrictions
-            .is_empty()
-        {
-            benihora.benihora.tract.source.other_constrictions = self
+        for voice in 0..self.voice_count() {
+            let other_constrictions = self
                 .other_constrictions
                 .iter()
-                .map(|x| (x.0, 10.0))
+                .map(|x| (self.tract_index(x.0), OPEN_CONSTRICTION))
                 .collect();
+            let benihora = self.voice_mut(voice);
+            if benihora
+                .benihora
+                .tract
+                .source
+                .other_constrictions
+                .is_empty()
+            {
+                benihora.benihora.tract.source.other_constrictions = other_constrictions;
+            }
         }
     }
 
@@ -312,3 +1066,50 @@ impl Synth {
         self.reset_required = true;
     }
 }
+
+#[test]
+fn test_overrides() {
+    let mut synth = Synth::new();
+    synth.ensure_benihora(8000.0);
+    synth.handle_event(&Event::ControlChange { cc: 2, value: 0.5 });
+    let main = synth.benihora.as_mut().unwrap();
+    main.routine_intensity = Some(0.9);
+    main.aspiration_level = Some(0.1);
+
+    // A note drops the overrides of the routines but keeps the breath controller
+    synth.handle_event(&Event::NoteOn {
+        note: 60,
+        velocity: 1.0,
+    });
+    let main = synth.benihora.as_ref().unwrap();
+    assert_eq!(main.routine_intensity, None);
+    assert_eq!(main.aspiration_level, None);
+    assert_eq!(main.intensity_target, Some(0.5));
+}
+
+#[test]
+fn test_voice_gain() {
+    let mut synth = Synth::new();
+    synth.polyphony = 8;
+    synth.ensure_benihora(8000.0);
+    let dtime = 1.0 / 8000.0;
+
+    // A single note is as loud as in the monophonic mode
+    synth.handle_event(&Event::NoteOn {
+        note: 60,
+        velocity: 1.0,
+    });
+    for _ in 0..4000 {
+        synth.process(dtime);
+    }
+    assert!((synth.voice_gain - 1.0).abs() < 1e-3);
+
+    synth.handle_event(&Event::NoteOn {
+        note: 64,
+        velocity: 1.0,
+    });
+    for _ in 0..4000 {
+        synth.process(dtime);
+    }
+    assert!((synth.voice_gain - 0.5f32.sqrt()).abs() < 1e-2);
+}
@@ -10,9 +10,10 @@ use self::{
 };
 use crate::{
     benihora_managed::Params,
-    synth::{Control, Synth},
+    control_mapping::{ControlMapping, Source, Target},
+    synth::{Control, InputMode, Synth},
 };
-use egui::{self, ScrollArea};
+use egui::{self, ComboBox, ScrollArea};
 
 pub fn show<P: Param>(
     ui: &mut egui::Ui,
@@ -93,7 +94,21 @@ pub fn show<P: Param>(
                     Some(default_params.frequency_pid.kd)
                 ));
                 ui.add(knob_param(vibrato_amount));
-                ui.add(knob_param(vibrato_rate));
+                if synth.benihora_params.vibrato_sync {
+                    let division = &mut synth.benihora_params.vibrato_division;
+                    ComboBox::from_id_source("vibrato_division")
+                        .width(48.0)
+                        .selected_text(division_name(*division))
+                        .show_ui(ui, |ui| {
+                            for (value, name) in VIBRATO_DIVISIONS {
+                                ui.selectable_value(division, value, name);
+                            }
+                        });
+                } else {
+                    ui.add(knob_param(vibrato_rate));
+                }
+                ui.checkbox(&mut synth.benihora_params.vibrato_sync, "Sync")
+                    .on_hover_text("Sync the vibrato to the host tempo");
                 ui.add(knob_param(frequency_wobble));
             });
             ui.horizontal(|ui| {
@@ -132,6 +147,15 @@ pub fn show<P: Param>(
                 }
             });
 
+            ui.horizontal(|ui| {
+                ui.add(knob(0.0..0.1, &mut synth.benihora_params.jitter, "Jitter", Some(default_params.jitter)));
+                ui.add(knob(0.0..0.5, &mut synth.benihora_params.shimmer, "Shimmer", Some(default_params.shimmer)));
+                ui.add(knob(0.0..1.0, &mut synth.benihora_params.creak, "Creak", Some(default_params.creak)))
+                    .on_hover_text("Alternates long and short pulses like vocal fry");
+                ui.add(knob(0.0..1.0, &mut synth.benihora_params.breath_leak, "Breath leak", Some(default_params.breath_leak)))
+                    .on_hover_text("Air leaking through the glottis");
+            });
+
             ui.add(egui::widgets::Checkbox::new(
                 &mut synth.benihora.as_mut().unwrap().intensity_pid_enabled,
                 "Use PID intensity",
@@ -154,8 +178,11 @@ pub fn show<P: Param>(
                     ui.add(knob_param(tongue_y));
                 }
                 crate::synth::Control::Internal => {
-                    let tract = &mut synth.benihora.as_mut().unwrap().tract;
-                    ui.add(knob(12.0..28.0, &mut tract.tongue_target.0, "Tongue x", None));
+                    let benihora = synth.benihora.as_mut().unwrap();
+                    let source = &benihora.benihora.tract.source;
+                    let range = source.scale_index(12.0)..source.scale_index(28.0);
+                    let tract = &mut benihora.tract;
+                    ui.add(knob(range, &mut tract.tongue_target.0, "Tongue x", None));
                     ui.add(knob(2.0..4.0, &mut tract.tongue_target.1, "Tongue y", None));
                     ui.add(knob_log(
                         0.1..100.0,
@@ -165,6 +192,15 @@ pub fn show<P: Param>(
                     ));
                 }
             });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut synth.benihora_params.spring_movement, "Springs")
+                    .on_hover_text("Checked: The articulators accelerate and overshoot like masses on springs\nUnchecked: The tract moves at a constant speed");
+                if synth.benihora_params.spring_movement {
+                    ui.add(knob_log(0.25..4.0, &mut synth.benihora_params.articulator_speed, "Articulator speed", Some(default_params.articulator_speed)));
+                    ui.add(knob_log(0.25..2.0, &mut synth.benihora_params.articulator_damping, "Articulator damping", Some(default_params.articulator_damping)))
+                        .on_hover_text("Lower values overshoot the targets");
+                }
+            });
 
             // ui.horizontal(|ui| {
             //     ui.add(
@@ -193,6 +229,38 @@ pub fn show<P: Param>(
                 ui.checkbox(&mut synth.benihora_params.always_sound, "Always")
                     .on_hover_text("Check to always produce sound");
             });
+            ui.horizontal(|ui| {
+                ui.horizontal(|ui| {
+                    if ui
+                        .add(
+                            egui::widgets::DragValue::new(&mut synth.polyphony).clamp_range(1..=8),
+                        )
+                        .changed()
+                    {
+                        synth.request_reset();
+                    }
+                    ui.label("Voices");
+                })
+                .response
+                .on_hover_text("Number of voices\n1: Monophonic with last note priority");
+                ui.add_enabled(
+                    synth.polyphony > 1,
+                    egui::widgets::Checkbox::new(&mut synth.shared_tract, "Shared tract"),
+                )
+                .on_hover_text("Checked: All voices follow the tract shape of the first voice\nUnchecked: Each voice runs its own routines on its own tract");
+            });
+            ui.horizontal(|ui| {
+                ComboBox::from_id_source("input_mode")
+                    .selected_text(synth.input_mode.name())
+                    .show_ui(ui, |ui| {
+                        for mode in InputMode::ALL {
+                            ui.selectable_value(&mut synth.input_mode, mode, mode.name());
+                        }
+                    });
+                ui.label("Input");
+            })
+            .response
+            .on_hover_text("Talkbox: The audio input is shaped by the tract of the first voice only\nFollower: The pitch and the volume of the audio input drive the voice");
 
             ui.label(egui::RichText::new(format!("Build {}", build_time::build_time_utc!("%C%m%d-%H%M%S"))).weak());
         });
@@ -202,19 +270,22 @@ pub fn show<P: Param>(
             let view_mode = ui
                 .data_mut(|d| d.get_persisted::<usize>(view_id).unwrap_or_default());
 
-            let view_mode_name = [
+            let view_mode_names = [
                 "Tract",
                 "Glottis plot",
                 "Glottis waveform",
                 "Routines",
                 "Key bindings",
+                "MIDI mapping",
+                "Presets",
                 "Frequency response",
-            ][view_mode];
+            ];
+            let view_mode_name = view_mode_names[view_mode];
             ui.horizontal(|ui| {
                 if ui.link(view_mode_name).clicked() {
                     ui.data_mut(|d| {
                         let view = d.get_persisted_mut_or_default::<usize>(view_id);
-                        *view = (*view + 1) % 5;
+                        *view = (*view + 1) % view_mode_names.len();
                     });
                 }
 
@@ -266,6 +337,12 @@ pub fn show<P: Param>(
                     show_key_bindings(ui, synth);
                 }
                 5 => {
+                    show_control_mappings(ui, synth);
+                }
+                6 => {
+                    show_presets(ui, synth);
+                }
+                7 => {
                     show_frequency_response(
                         ui,
                         &benihora_tract_frequency_response(
@@ -401,3 +478,330 @@ fn show_key_bindings(ui: &mut egui::Ui, synth: &mut Synth) {
             ui.label("...");
         });
 }
+
+fn show_control_mappings(ui: &mut egui::Ui, synth: &mut Synth) {
+    let mut remove = None;
+    let mut retargeted = false;
+    ScrollArea::vertical()
+        .auto_shrink([false, true])
+        .show(ui, |ui| {
+            for (i, mapping) in synth.control_mappings.iter_mut().enumerate() {
+                ui.push_id(i, |ui| {
+                    ui.horizontal(|ui| {
+                        ComboBox::from_id_source("source")
+                            .selected_text(match mapping.source {
+                                Source::ControlChange(_) => "CC".to_string(),
+                                source => source.name(),
+                            })
+                            .show_ui(ui, |ui| {
+                                for source in [
+                                    Source::ControlChange(1),
+                                    Source::ChannelPressure,
+                                    Source::PolyPressure,
+                                ] {
+                                    let selected = std::mem::discriminant(&mapping.source)
+                                        == std::mem::discriminant(&source);
+                                    if ui.selectable_label(selected, source.name()).clicked()
+                                        && !selected
+                                    {
+                                        mapping.source = source;
+                                    }
+                                }
+                            });
+                        if let Source::ControlChange(cc) = &mut mapping.source {
+                            ui.add(egui::DragValue::new(cc).clamp_range(0..=127));
+                        }
+                        ui.label("→");
+                        ComboBox::from_id_source("target")
+                            .selected_text(mapping.target.name())
+                            .show_ui(ui, |ui| {
+                                for target in Target::ALL {
+                                    let selected = std::mem::discriminant(&mapping.target)
+                                        == std::mem::discriminant(&target);
+                                    if ui.selectable_label(selected, target.name()).clicked()
+                                        && !selected
+                                    {
+                                        retargeted |= mapping.target == Target::Intensity;
+                                        *mapping = ControlMapping::new(mapping.source, target);
+                                    }
+                                }
+                            });
+                        if let Target::Constriction(i) = &mut mapping.target {
+                            let mut n = *i + 1;
+                            ui.add(
+                                egui::DragValue::new(&mut n)
+                                    .clamp_range(1..=synth.other_constrictions.len().max(1)),
+                            );
+                            *i = n - 1;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let speed = (mapping.max - mapping.min).abs().max(1.0) * 0.01;
+                        ui.add(egui::DragValue::new(&mut mapping.min).speed(speed));
+                        ui.label("-");
+                        ui.add(egui::DragValue::new(&mut mapping.max).speed(speed));
+                        if ui.small_button("×").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                });
+                ui.separator();
+            }
+            if ui.button("Add").clicked() {
+                synth.control_mappings.push(ControlMapping::new(
+                    Source::ControlChange(1),
+                    Target::Tenseness,
+                ));
+            }
+        });
+
+    if let Some(i) = remove {
+        let mapping = synth.control_mappings.remove(i);
+        if mapping.target == Target::Intensity {
+            synth.release_control_intensity();
+        }
+    }
+    if retargeted {
+        synth.release_control_intensity();
+    }
+}
+
+fn show_presets(ui: &mut egui::Ui, synth: &mut Synth) {
+    let id = ui.make_persistent_id("Presets");
+    let name_id = id.with("name");
+    let mut name = ui.data(|d| d.get_temp::<String>(name_id).unwrap_or_default());
+    let mut load = None;
+    let mut remove = None;
+
+    ui.horizontal(|ui| {
+        ui.add(egui::TextEdit::singleline(&mut name).desired_width(100.0));
+        if ui
+            .button("Save")
+            .on_hover_text("Add the current settings to the bank")
+            .clicked()
+        {
+            let name = if name.is_empty() {
+                format!("Preset {}", synth.presets.len() + 1)
+            } else {
+                name.clone()
+            };
+            synth.presets.push(synth.to_preset(name));
+        }
+    });
+    ui.data_mut(|d| d.insert_temp(name_id, name));
+
+    #[cfg(not(target_arch = "wasm32"))]
+    show_preset_file(ui, id, synth);
+    #[cfg(not(target_arch = "wasm32"))]
+    show_score_file(ui, id.with("score"), synth);
+    #[cfg(not(target_arch = "wasm32"))]
+    show_recording_file(ui, id.with("recording"), synth);
+
+    ui.separator();
+    ScrollArea::vertical()
+        .auto_shrink([false, true])
+        .show(ui, |ui| {
+            for (i, preset) in synth.presets.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{:>3}", i))
+                        .on_hover_text("Program number");
+                    if ui.link(&preset.name).clicked() {
+                        load = Some(i);
+                    }
+                    if ui.small_button("×").clicked() {
+                        remove = Some(i);
+                    }
+                });
+            }
+        });
+
+    if let Some(i) = load {
+        synth.load_preset(i);
+    }
+    if let Some(i) = remove {
+        synth.presets.remove(i);
+    }
+}
+
+/// Beats per vibrato cycle
+const VIBRATO_DIVISIONS: [(f32, &str); 7] = [
+    (1.0, "1/4"),
+    (0.5, "1/8"),
+    (1.0 / 3.0, "1/8T"),
+    (0.25, "1/16"),
+    (1.0 / 6.0, "1/16T"),
+    (0.125, "1/32"),
+    (1.0 / 12.0, "1/32T"),
+];
+
+fn division_name(division: f32) -> String {
+    VIBRATO_DIVISIONS
+        .iter()
+        .find(|(value, _)| *value == division)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| format!("{:.3}", division))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn show_score_file(ui: &mut egui::Ui, id: egui::Id, synth: &mut Synth) {
+    let path_id = id.with("path");
+    let message_id = id.with("message");
+    let mut path = ui.data(|d| d.get_temp::<String>(path_id).unwrap_or_default());
+    let mut message = ui.data(|d| d.get_temp::<String>(message_id).unwrap_or_default());
+
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut path)
+                .hint_text("score.json")
+                .desired_width(100.0),
+        );
+        if ui
+            .button("Play")
+            .on_hover_text("Play the score file, JSON or RON, on the first voice")
+            .clicked()
+        {
+            message = match crate::score::Score::load(&path) {
+                Ok(score) => {
+                    synth.play_score(score);
+                    String::new()
+                }
+                Err(e) => e.to_string(),
+            };
+        }
+        if ui
+            .add_enabled(synth.is_score_playing(), egui::Button::new("Stop"))
+            .clicked()
+        {
+            synth.stop_score();
+        }
+    });
+    if !message.is_empty() {
+        ui.label(egui::RichText::new(&message).weak());
+    }
+
+    ui.data_mut(|d| {
+        d.insert_temp(path_id, path);
+        d.insert_temp(message_id, message);
+    });
+}
+
+/// The score estimated from a recording, set by the worker thread
+#[cfg(not(target_arch = "wasm32"))]
+type AnalysisJob = std::sync::Arc<std::sync::Mutex<Option<std::io::Result<crate::score::Score>>>>;
+
+#[cfg(not(target_arch = "wasm32"))]
+fn show_recording_file(ui: &mut egui::Ui, id: egui::Id, synth: &mut Synth) {
+    let path_id = id.with("path");
+    let message_id = id.with("message");
+    let job_id = id.with("job");
+    let mut path = ui.data(|d| d.get_temp::<String>(path_id).unwrap_or_default());
+    let mut message = ui.data(|d| d.get_temp::<String>(message_id).unwrap_or_default());
+
+    let job = ui.data(|d| d.get_temp::<AnalysisJob>(job_id));
+    let mut running = job.is_some();
+    if let Some(result) = job.and_then(|job| job.lock().unwrap().take()) {
+        message = match result {
+            Ok(score) => {
+                synth.play_score(score);
+                String::new()
+            }
+            Err(e) => e.to_string(),
+        };
+        ui.data_mut(|d| d.remove::<AnalysisJob>(job_id));
+        running = false;
+    }
+
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut path)
+                .hint_text("speech.wav")
+                .desired_width(100.0),
+        );
+        if running {
+            ui.spinner();
+        } else if ui
+            .button("Re-voice")
+            .on_hover_text(
+                "Estimate the pitch, the loudness and the tongue of the recording and play them",
+            )
+            .clicked()
+        {
+            // The analysis takes a while, so it runs off the lock of the synth
+            let job = AnalysisJob::default();
+            ui.data_mut(|d| d.insert_temp(job_id, job.clone()));
+            let path = path.clone();
+            let ctx = ui.ctx().clone();
+            std::thread::spawn(move || {
+                let result = crate::analysis::read_wav(&path).map(|(signal, sample_rate)| {
+                    let name = std::path::Path::new(&path)
+                        .file_stem()
+                        .map(|s| s.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    crate::analysis::analyze(&signal, sample_rate).to_score(name)
+                });
+                *job.lock().unwrap() = Some(result);
+                ctx.request_repaint();
+            });
+        }
+    });
+    if !message.is_empty() {
+        ui.label(egui::RichText::new(&message).weak());
+    }
+
+    ui.data_mut(|d| {
+        d.insert_temp(path_id, path);
+        d.insert_temp(message_id, message);
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn show_preset_file(ui: &mut egui::Ui, id: egui::Id, synth: &mut Synth) {
+    let path_id = id.with("path");
+    let message_id = id.with("message");
+    let mut path = ui.data(|d| d.get_temp::<String>(path_id).unwrap_or_default());
+    let mut message = ui.data(|d| d.get_temp::<String>(message_id).unwrap_or_default());
+
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut path)
+                .hint_text("preset.json")
+                .desired_width(100.0),
+        );
+        if ui
+            .button("Export")
+            .on_hover_text("Save the current settings to the file")
+            .clicked()
+        {
+            let name = std::path::Path::new(&path)
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            message = match synth.to_preset(name).save(&path) {
+                Ok(()) => "Saved".to_string(),
+                Err(e) => e.to_string(),
+            };
+        }
+        if ui
+            .button("Import")
+            .on_hover_text("Load the file and add it to the bank")
+            .clicked()
+        {
+            message = match crate::preset::Preset::load(&path) {
+                Ok(preset) => {
+                    synth.apply_preset(&preset);
+                    synth.presets.push(preset);
+                    "Loaded".to_string()
+                }
+                Err(e) => e.to_string(),
+            };
+        }
+    });
+    if !message.is_empty() {
+        ui.label(egui::RichText::new(&message).weak());
+    }
+
+    ui.data_mut(|d| {
+        d.insert_temp(path_id, path);
+        d.insert_temp(message_id, message);
+    });
+}
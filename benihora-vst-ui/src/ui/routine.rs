@@ -1,8 +1,10 @@
 use super::knob::{knob, knob_log};
 use crate::{
-    routine::{Event, Routine, TongueIndex},
+    routine::{Event, Ramp, Routine, TimeUnit, TongueIndex},
+    score::Curve,
     synth::Synth,
 };
+use benihora::tract::{DEFAULT_MOUTH_LENGTH, DEFAULT_TONGUE};
 use egui::{self, Button, ComboBox, ScrollArea};
 
 pub fn show_routines(ui: &mut egui::Ui, synth: &mut Synth) {
@@ -26,6 +28,17 @@ pub fn show_routines(ui: &mut egui::Ui, synth: &mut Synth) {
                     }
                     // ui.text_edit_singleline(&mut synth.routines[index].name);
                     ui.label(format!("Routine {}", index + 1));
+                    let time_unit = &mut synth.routines[index].time_unit;
+                    ComboBox::from_id_source("time_unit")
+                        .width(64.0)
+                        .selected_text(time_unit.name())
+                        .show_ui(ui, |ui| {
+                            for unit in TimeUnit::ALL {
+                                ui.selectable_value(time_unit, unit, unit.name());
+                            }
+                        })
+                        .response
+                        .on_hover_text("Unit of the event times");
                     if ui.button("▶").clicked() {
                         preview_routine = Some(index);
                     }
@@ -67,6 +80,7 @@ pub fn show_routines(ui: &mut egui::Ui, synth: &mut Synth) {
                         .unwrap_or_default()
                 });
                 let mut remove_event = None;
+                let routines = synth.routines.len();
                 for (i, ev) in synth.routines[index].events.iter_mut().enumerate() {
                     ui.horizontal(|ui| {
                         ui.add(
@@ -91,6 +105,7 @@ pub fn show_routines(ui: &mut egui::Ui, synth: &mut Synth) {
                             ui,
                             synth.tongue_poses.len(),
                             synth.other_constrictions.len(),
+                            routines,
                         );
                     }
                     ui.separator();
@@ -220,20 +235,29 @@ fn add_event_ui<'a>(synth: &'a mut Synth, index: usize) -> impl FnMut(&mut egui:
                 Event::Constriction {
                     i: 0,
                     strength: Some(1.0),
+                    ramp: None,
                 },
             ));
             ui.close_menu();
         }
         if ui.button("Velum").clicked() {
-            synth.routines[index]
-                .events
-                .push((0.0, Event::Velum { openness: 1.0 }));
+            synth.routines[index].events.push((
+                0.0,
+                Event::Velum {
+                    openness: 1.0,
+                    ramp: None,
+                },
+            ));
             ui.close_menu();
         }
         if ui.button("Pitch").clicked() {
-            synth.routines[index]
-                .events
-                .push((0.0, Event::Pitch { value: 0.0 }));
+            synth.routines[index].events.push((
+                0.0,
+                Event::Pitch {
+                    value: 0.0,
+                    ramp: None,
+                },
+            ));
             ui.close_menu();
         }
         if ui.button("Sound").clicked() {
@@ -248,10 +272,65 @@ fn add_event_ui<'a>(synth: &'a mut Synth, index: usize) -> impl FnMut(&mut egui:
                 .push((0.0, Event::ForceDiameter));
             ui.close_menu();
         }
+        ui.separator();
+        let events = [
+            Event::TongueTo {
+                index: DEFAULT_TONGUE.0,
+                diameter: DEFAULT_TONGUE.1,
+                speed: None,
+            },
+            Event::ConstrictionPosition {
+                i: 0,
+                position: 25.0,
+                ramp: None,
+            },
+            Event::Tenseness {
+                value: 0.6,
+                ramp: None,
+            },
+            Event::Loudness {
+                value: 0.8,
+                ramp: None,
+            },
+            Event::Intensity {
+                value: Some(0.9),
+                ramp: None,
+            },
+            Event::Aspiration {
+                level: Some(1.0),
+                ramp: None,
+            },
+            Event::VibratoDepth {
+                value: Some(0.005),
+                ramp: None,
+            },
+            Event::VibratoRate {
+                value: Some(6.0),
+                ramp: None,
+            },
+            Event::Routine { index },
+        ];
+        for event in events {
+            if ui.button(event.name()).clicked() {
+                // A routine triggering itself loops, once a beat or a second by default
+                let delay = match event {
+                    Event::Routine { .. } => 1.0,
+                    _ => 0.0,
+                };
+                synth.routines[index].events.push((delay, event));
+                ui.close_menu();
+            }
+        }
     }
 }
 
-fn event_ui(ev: &mut Event, ui: &mut egui::Ui, tongue_poses: usize, other_constrictions: usize) {
+fn event_ui(
+    ev: &mut Event,
+    ui: &mut egui::Ui,
+    tongue_poses: usize,
+    other_constrictions: usize,
+    routines: usize,
+) {
     match ev {
         Event::Tongue { index, speed } => {
             ui.horizontal(|ui| {
@@ -286,7 +365,7 @@ fn event_ui(ev: &mut Event, ui: &mut egui::Ui, tongue_poses: usize, other_constr
                 }
             });
         }
-        Event::Constriction { i, strength } => {
+        Event::Constriction { i, strength, ramp } => {
             ui.horizontal(|ui| {
                 ComboBox::from_id_source("other_constriction")
                     .selected_text(format!("Constriction {}", *i))
@@ -310,18 +389,223 @@ fn event_ui(ev: &mut Event, ui: &mut egui::Ui, tongue_poses: usize, other_constr
                         *strength = Some(1.0);
                     }
                 }
+                ramp_ui(ui, ramp);
             });
         }
-        Event::Velum { openness } => {
-            ui.add(knob(0.0..1.0, openness, "Openness", None));
+        Event::Velum { openness, ramp } => {
+            ui.horizontal(|ui| {
+                ui.add(knob(0.0..1.0, openness, "Openness", None));
+                ramp_ui(ui, ramp);
+            });
         }
-        Event::Pitch { value } => {
-            ui.add(knob(-12.0..12.0, value, "Pitch", Some(0.0)));
+        Event::Pitch { value, ramp } => {
+            ui.horizontal(|ui| {
+                ui.add(knob(-12.0..12.0, value, "Pitch", Some(0.0)));
+                ramp_ui(ui, ramp);
+            });
         }
         Event::Sound { sound } => {
             ui.checkbox(sound, "Sound");
         }
         Event::ForceDiameter => {}
+        Event::TongueTo {
+            index,
+            diameter,
+            speed,
+        } => {
+            ui.horizontal(|ui| {
+                ui.add(knob(12.0..28.0, index, "Tongue x", None));
+                ui.add(knob(2.0..4.0, diameter, "Tongue y", None));
+                let mut remove_speed = false;
+                if let Some(speed) = speed {
+                    ui.add(knob_log(1.0..200.0, speed, "Speed", None))
+                        .context_menu(|ui| {
+                            if ui.button("Remove").clicked() {
+                                remove_speed = true;
+                                ui.close_menu();
+                            }
+                        });
+                }
+                if remove_speed {
+                    *speed = None;
+                }
+                if speed.is_none() && ui.button("Set speed").clicked() {
+                    *speed = Some(20.0);
+                }
+            });
+        }
+        Event::ConstrictionPosition { i, position, ramp } => {
+            ui.horizontal(|ui| {
+                ComboBox::from_id_source("other_constriction")
+                    .selected_text(format!("Constriction {}", *i))
+                    .show_ui(ui, |ui| {
+                        for j in 0..other_constrictions {
+                            ui.selectable_value(i, j, format!("Constriction {}", j));
+                        }
+                    });
+                ui.add(knob(
+                    2.0..DEFAULT_MOUTH_LENGTH as f32,
+                    position,
+                    "Position",
+                    None,
+                ));
+                ramp_ui(ui, ramp);
+            });
+        }
+        Event::Tenseness { value, ramp } => {
+            ui.horizontal(|ui| {
+                ui.add(knob(0.0..1.0, value, "Tenseness", None));
+                ramp_ui(ui, ramp);
+            });
+        }
+        Event::Loudness { value, ramp } => {
+            ui.horizontal(|ui| {
+                ui.add(knob(0.0..1.0, value, "Loudness", None));
+                ramp_ui(ui, ramp);
+            });
+        }
+        Event::Intensity { value, ramp } => {
+            ui.horizontal(|ui| {
+                override_ui(ui, value, 0.9, |ui, v| {
+                    ui.add(knob(0.0..1.0, v, "Intensity", None))
+                });
+                ramp_ui(ui, ramp);
+            });
+        }
+        Event::Aspiration { level, ramp } => {
+            ui.horizontal(|ui| {
+                override_ui(ui, level, 1.0, |ui, v| {
+                    ui.add(knob(0.0..10.0, v, "Aspiration", None))
+                });
+                ramp_ui(ui, ramp);
+            });
+        }
+        Event::VibratoDepth { value, ramp } => {
+            ui.horizontal(|ui| {
+                override_ui(ui, value, 0.005, |ui, v| {
+                    ui.add(knob(0.0..0.1, v, "Vibrato depth", None))
+                });
+                ramp_ui(ui, ramp);
+            });
+        }
+        Event::VibratoRate { value, ramp } => {
+            ui.horizontal(|ui| {
+                override_ui(ui, value, 6.0, |ui, v| {
+                    ui.add(knob_log(0.1..20.0, v, "Vibrato rate", None))
+                });
+                ramp_ui(ui, ramp);
+            });
+        }
+        Event::Routine { index } => {
+            ComboBox::from_id_source("routine")
+                .selected_text(format!("Routine {}", *index + 1))
+                .show_ui(ui, |ui| {
+                    for j in 0..routines {
+                        ui.selectable_value(index, j, format!("Routine {}", j + 1));
+                    }
+                });
+        }
+    }
+}
+
+const CURVES: [(Curve, &str); 5] = [
+    (Curve::Linear, "Linear"),
+    (Curve::Smoothstep, "Smooth"),
+    (
+        Curve::Bezier {
+            x1: 0.42,
+            y1: 0.0,
+            x2: 1.0,
+            y2: 1.0,
+        },
+        "Ease in",
+    ),
+    (
+        Curve::Bezier {
+            x1: 0.0,
+            y1: 0.0,
+            x2: 0.58,
+            y2: 1.0,
+        },
+        "Ease out",
+    ),
+    (
+        Curve::Bezier {
+            x1: 0.42,
+            y1: 0.0,
+            x2: 0.58,
+            y2: 1.0,
+        },
+        "Ease in-out",
+    ),
+];
+
+/// Edits the value of an event that overrides a parameter. None gives the control back.
+fn override_ui(
+    ui: &mut egui::Ui,
+    value: &mut Option<f32>,
+    default: f32,
+    add_knob: impl FnOnce(&mut egui::Ui, &mut f32) -> egui::Response,
+) {
+    let mut release = false;
+    if let Some(value) = value {
+        add_knob(ui, value).context_menu(|ui| {
+            if ui.button("Release").clicked() {
+                release = true;
+                ui.close_menu();
+            }
+        });
+    }
+    if release {
+        *value = None;
+    }
+    if value.is_none()
+        && ui
+            .button("Set")
+            .on_hover_text("Released: The parameter is used")
+            .clicked()
+    {
+        *value = Some(default);
+    }
+}
+
+fn ramp_ui(ui: &mut egui::Ui, ramp: &mut Option<Ramp>) {
+    let mut remove_ramp = false;
+    if let Some(Ramp { duration, curve }) = ramp {
+        ui.add(knob_log(0.001..4.0, duration, "Ramp", None))
+            .on_hover_text("Duration of the ramp in the time unit of the routine")
+            .context_menu(|ui| {
+                if ui.button("Remove").clicked() {
+                    remove_ramp = true;
+                    ui.close_menu();
+                }
+            });
+        let name = CURVES
+            .iter()
+            .find(|(c, _)| c == curve)
+            .map_or("Custom", |(_, name)| name);
+        ComboBox::from_id_source("curve")
+            .width(80.0)
+            .selected_text(name)
+            .show_ui(ui, |ui| {
+                for (c, name) in CURVES {
+                    ui.selectable_value(curve, c, name);
+                }
+            });
+    }
+    if remove_ramp {
+        *ramp = None;
+    }
+    if ramp.is_none()
+        && ui
+            .button("Ramp")
+            .on_hover_text("Move to the value gradually")
+            .clicked()
+    {
+        *ramp = Some(Ramp {
+            duration: 0.03,
+            curve: Curve::Linear,
+        });
     }
 }
 
@@ -347,6 +631,7 @@ fn preset() -> &'static [Routine] {
             Routine {
                 name: "Empty".to_string(),
                 events: vec![],
+                time_unit: TimeUnit::Seconds,
             },
             Routine {
                 name: "Tongue move".to_string(),
@@ -366,6 +651,7 @@ fn preset() -> &'static [Routine] {
                         },
                     ),
                 ],
+                time_unit: TimeUnit::Seconds,
             },
             Routine {
                 name: "Tap".to_string(),
@@ -376,6 +662,7 @@ fn preset() -> &'static [Routine] {
                         Event::Constriction {
                             i: 1,
                             strength: Some(0.7),
+                            ramp: None,
                         },
                     ),
                     (0.0, Event::ForceDiameter),
@@ -384,65 +671,117 @@ fn preset() -> &'static [Routine] {
                         Event::Constriction {
                             i: 1,
                             strength: None,
+                            ramp: None,
                         },
                     ),
                     (0.01, Event::Sound { sound: true }),
                 ],
+                time_unit: TimeUnit::Seconds,
             },
             Routine {
                 name: "Nasal".to_string(),
                 events: vec![
-                    (0.0, Event::Velum { openness: 1.0 }),
+                    (
+                        0.0,
+                        Event::Velum {
+                            openness: 1.0,
+                            ramp: None,
+                        },
+                    ),
                     (
                         0.0,
                         Event::Constriction {
                             i: 0,
                             strength: Some(1.0),
+                            ramp: None,
+                        },
+                    ),
+                    (
+                        0.2,
+                        Event::Velum {
+                            openness: 0.0,
+                            ramp: None,
                         },
                     ),
-                    (0.2, Event::Velum { openness: 0.0 }),
                     (
                         0.0,
                         Event::Constriction {
                             i: 0,
                             strength: None,
+                            ramp: None,
                         },
                     ),
                 ],
+                time_unit: TimeUnit::Seconds,
             },
             Routine {
                 name: "Humming".to_string(),
                 events: vec![
-                    (0.0, Event::Velum { openness: 1.0 }),
+                    (
+                        0.0,
+                        Event::Velum {
+                            openness: 1.0,
+                            ramp: None,
+                        },
+                    ),
                     (
                         0.0,
                         Event::Constriction {
                             i: 0,
                             strength: Some(1.0),
+                            ramp: None,
                         },
                     ),
                 ],
+                time_unit: TimeUnit::Seconds,
             },
             Routine {
                 name: "Humming off".to_string(),
                 events: vec![
-                    (0.0, Event::Velum { openness: 0.0 }),
+                    (
+                        0.0,
+                        Event::Velum {
+                            openness: 0.0,
+                            ramp: None,
+                        },
+                    ),
                     (
                         0.0,
                         Event::Constriction {
                             i: 0,
                             strength: None,
+                            ramp: None,
                         },
                     ),
                 ],
+                time_unit: TimeUnit::Seconds,
             },
             Routine {
                 name: "Trill".to_string(),
                 events: vec![
-                    (0.0, Event::Pitch { value: 0.0 }),
-                    (0.06, Event::Pitch { value: 1.0 }),
-                    (0.06, Event::Pitch { value: 0.0 }),
+                    (
+                        0.0,
+                        Event::Pitch {
+                            value: 0.0,
+                            ramp: None,
+                        },
+                    ),
+                    (
+                        0.06,
+                        Event::Pitch {
+                            value: 1.0,
+                            ramp: None,
+                        },
+                    ),
+                    (
+                        0.06,
+                        Event::Pitch {
+                            value: 0.0,
+                            ramp: None,
+                        },
+                    ),
                 ],
+                time_unit: TimeUnit::Seconds,
             },
             Routine {
                 name: "Random tongue".to_string(),
@@ -453,6 +792,7 @@ fn preset() -> &'static [Routine] {
                         speed: None,
                     },
                 )],
+                time_unit: TimeUnit::Seconds,
             },
         ]
     })
@@ -1,4 +1,5 @@
 use crate::{synth::Synth, FFT_PLANNER};
+use benihora::{formant::Formant, tract::Diameter};
 use rustfft::num_complex::Complex32;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -18,6 +19,10 @@ pub fn show_tract(ui: &mut egui::Ui, synth: &mut Synth) -> egui::Response {
         ..
     } = synth;
     let benihora = benihora.as_mut().unwrap();
+    let width = benihora.benihora.tract.source.length as f32 + 1.0;
+    // Poses and constrictions are in the default tract
+    let scale = benihora.benihora.tract.source.scale_index(1.0);
+    let tongue_x_range = TONGUE_X_RANGE.start * scale..TONGUE_X_RANGE.end * scale;
 
     let tract_edit_id = egui::Id::new(TRACT_EDIT_ID);
     let tract_edit = ui.data(|d| d.get_temp::<bool>(tract_edit_id).unwrap_or_default());
@@ -31,7 +36,7 @@ pub fn show_tract(ui: &mut egui::Ui, synth: &mut Synth) -> egui::Response {
         let tract = &benihora.benihora.tract;
         let (_id, rect) = ui.allocate_space(egui::vec2(180.0, 180.0));
         let to_screen = egui::emath::RectTransform::from_to(
-            egui::Rect::from_x_y_ranges(0.0..=45.0, 0.0..=10.0),
+            egui::Rect::from_x_y_ranges(0.0..=width, 0.0..=10.0),
             rect,
         );
 
@@ -81,8 +86,8 @@ pub fn show_tract(ui: &mut egui::Ui, synth: &mut Synth) -> egui::Response {
         );
 
         let tongue_area_rect = egui::Rect::from_min_max(
-            to_screen * egui::pos2(TONGUE_X_RANGE.start, TONGUE_Y_RANGE.start + dy),
-            to_screen * egui::pos2(TONGUE_X_RANGE.end, TONGUE_Y_RANGE.end + dy),
+            to_screen * egui::pos2(tongue_x_range.start, TONGUE_Y_RANGE.start + dy),
+            to_screen * egui::pos2(tongue_x_range.end, TONGUE_Y_RANGE.end + dy),
         );
         if !tract_edit
             && tongue_area_rect
@@ -101,7 +106,7 @@ pub fn show_tract(ui: &mut egui::Ui, synth: &mut Synth) -> egui::Response {
 
         // tongue
         for (i, pos) in tongue_poses.iter().enumerate() {
-            let pos = to_screen * egui::pos2(pos.0, (pos.1) + dy);
+            let pos = to_screen * egui::pos2(pos.0 * scale, (pos.1) + dy);
             if pointer.map(|p| (p - pos).length() < 5.0).unwrap_or(false) {
                 if tract_edit {
                     hover = Some(Part::TonguePoint(i));
@@ -125,7 +130,7 @@ pub fn show_tract(ui: &mut egui::Ui, synth: &mut Synth) -> egui::Response {
 
         // constriction
         for (i, &oc) in other_constrictions.iter().enumerate() {
-            let pos = to_screen * egui::pos2(oc.0, (oc.1) + dy);
+            let pos = to_screen * egui::pos2(oc.0 * scale, (oc.1) + dy);
             if pointer.map(|p| (p - pos).length() < 5.0).unwrap_or(false) {
                 hover = Some(Part::Constriction(i));
                 ui.painter()
@@ -183,12 +188,46 @@ pub fn show_tract(ui: &mut egui::Ui, synth: &mut Synth) -> egui::Response {
             .collect();
         let stroke = egui::Stroke::new(1.0, egui::Color32::DARK_GRAY);
         ui.painter().add(egui::Shape::line(points, stroke));
+
+        // formants, recomputed only when the target shape changes
+        let formants_id = ui.make_persistent_id("tract_formants");
+        let cache = ui.data(|d| d.get_temp::<(Diameter, Vec<Formant>)>(formants_id));
+        let formants = match cache {
+            Some((diameter, formants)) if diameter == tract.target_diameter => formants,
+            _ => {
+                let formants = target_formants(&benihora.benihora);
+                ui.data_mut(|d| {
+                    d.insert_temp(
+                        formants_id,
+                        (tract.target_diameter.clone(), formants.clone()),
+                    )
+                });
+                formants
+            }
+        };
+        for (i, formant) in formants.iter().enumerate() {
+            let x = formant.frequency / max_frequency;
+            ui.painter().line_segment(
+                [
+                    to_screen * egui::pos2(x, 0.8),
+                    to_screen * egui::pos2(x, 1.0),
+                ],
+                egui::Stroke::new(1.0, egui::Color32::LIGHT_BLUE.linear_multiply(0.5)),
+            );
+            ui.painter().text(
+                to_screen * egui::pos2(x, 0.8),
+                egui::Align2::CENTER_BOTTOM,
+                format!("F{} {:.0}", i + 1, formant.frequency),
+                egui::FontId::proportional(9.0),
+                egui::Color32::LIGHT_BLUE.linear_multiply(0.5),
+            );
+        }
     });
 
     let res = ui.allocate_rect(res.response.rect, egui::Sense::click_and_drag());
     let from_screen = egui::emath::RectTransform::from_to(
         res.rect,
-        egui::Rect::from_x_y_ranges(0.0..=45.0, 0.0..=10.0),
+        egui::Rect::from_x_y_ranges(0.0..=width, 0.0..=10.0),
     );
     if res.drag_started() {
         drag_mode = match hover {
@@ -231,7 +270,7 @@ pub fn show_tract(ui: &mut egui::Ui, synth: &mut Synth) -> egui::Response {
                     //     .source
                     //     .tongue_clamp(pos.x as f32, (pos.y - dy) as f32);
                     benihora.tract.tongue_target = (
-                        (pos.x).clamp(TONGUE_X_RANGE.start, TONGUE_X_RANGE.end),
+                        (pos.x).clamp(tongue_x_range.start, tongue_x_range.end),
                         (pos.y - dy).clamp(TONGUE_Y_RANGE.start, TONGUE_Y_RANGE.end),
                     );
                 }
@@ -239,16 +278,16 @@ pub fn show_tract(ui: &mut egui::Ui, synth: &mut Synth) -> egui::Response {
             Some(Part::TonguePoint(ti)) => {
                 if let Some(pos) = pointer {
                     let pos = from_screen * pos;
-                    tongue_poses[ti] = (pos.x, pos.y - dy);
+                    tongue_poses[ti] = (pos.x / scale, pos.y - dy);
                 }
             }
             Some(Part::Constriction(ci)) => {
                 if let Some(pos) = pointer {
                     let pos = from_screen * pos;
                     if tract_edit {
-                        other_constrictions[ci] = (pos.x, pos.y - dy);
+                        other_constrictions[ci] = (pos.x / scale, pos.y - dy);
                     } else {
-                        let x = other_constrictions[ci].0;
+                        let x = other_constrictions[ci].0 * scale;
                         benihora.benihora.tract.source.other_constrictions[ci] = (x, pos.y - dy);
                     }
                 }
@@ -258,14 +297,100 @@ pub fn show_tract(ui: &mut egui::Ui, synth: &mut Synth) -> egui::Response {
     }
     ui.data_mut(|d| d.insert_temp(drag_mode_id, drag_mode));
 
+    // The fit takes seconds, and there are no threads to run it on in the browser
+    #[cfg(not(target_arch = "wasm32"))]
+    if tract_edit && !tongue_poses.is_empty() {
+        let fit_id = ui.make_persistent_id("tract_fit");
+        let (mut pose, mut targets) = ui.data(|d| {
+            d.get_temp::<(usize, [f32; 3])>(fit_id)
+                .unwrap_or((0, [500.0, 1500.0, 2500.0]))
+        });
+        pose = pose.min(tongue_poses.len() - 1);
+
+        // The fit takes a while, so it runs on a copy of the voice without the synth locked
+        let job_id = ui.make_persistent_id("tract_fit_job");
+        let job = ui.data(|d| d.get_temp::<FitJob>(job_id));
+        let mut running = job.is_some();
+        if let Some((fitted_pose, tongue)) = job.and_then(|job| job.lock().unwrap().take()) {
+            if let Some(pose) = tongue_poses.get_mut(fitted_pose) {
+                *pose = tongue;
+            }
+            ui.data_mut(|d| d.remove::<FitJob>(job_id));
+            running = false;
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut pose)
+                    .prefix("Tongue ")
+                    .clamp_range(0..=tongue_poses.len() - 1),
+            );
+            for (i, target) in targets.iter_mut().enumerate() {
+                ui.add(
+                    egui::DragValue::new(target)
+                        .prefix(format!("F{} ", i + 1))
+                        .speed(10.0)
+                        .clamp_range(100.0..=6000.0),
+                );
+            }
+            if running {
+                ui.spinner();
+            } else if ui
+                .small_button("Fit")
+                .on_hover_text("Move the tongue point to match the formants")
+                .clicked()
+            {
+                let mut benihora = benihora.benihora.clone();
+                benihora.tract.source.other_constrictions.clear();
+                let job = FitJob::default();
+                ui.data_mut(|d| d.insert_temp(job_id, job.clone()));
+                let ctx = ui.ctx().clone();
+                std::thread::spawn(move || {
+                    let tongue = benihora::fit::fit_shape(&benihora, &targets).tongue;
+                    *job.lock().unwrap() = Some((pose, (tongue.0 / scale, tongue.1)));
+                    ctx.request_repaint();
+                });
+            }
+        });
+        ui.data_mut(|d| d.insert_temp(fit_id, (pose, targets)));
+    }
+
     res
 }
 
+/// The tongue pose and the fitted tongue once the fit is done
+#[cfg(not(target_arch = "wasm32"))]
+type FitJob = std::sync::Arc<std::sync::Mutex<Option<(usize, (f32, f32))>>>;
+
+/// In the default tract
 const TONGUE_X_RANGE: std::ops::Range<f32> = 12.0..28.0;
 const TONGUE_Y_RANGE: std::ops::Range<f32> = 2.0..4.0;
 
 pub const TRACT_EDIT_ID: &str = "benihora_tract_edit";
 
+/// F1-F5 of the target shape of the tract by an FFT of its impulse response
+fn target_formants(benihora: &benihora::Benihora) -> Vec<Formant> {
+    let mut benihora = benihora.clone();
+    let tract = &mut benihora.tract;
+    tract.current_diameter = tract.target_diameter.clone();
+    tract.update_block(0.0);
+    let (response, sample_rate) = benihora::tract_impulse_response(8192, &benihora);
+
+    // Zero-padded so that the bins are narrower than 5 Hz
+    let n = ((sample_rate / 5.0) as usize)
+        .max(response.len())
+        .next_power_of_two();
+    let fft = FFT_PLANNER.with(|planner| planner.borrow_mut().plan_fft_forward(n));
+    let mut buf: Vec<Complex32> = response.iter().map(|&x| Complex32::from(x)).collect();
+    buf.resize(n, Complex32::default());
+    fft.process(&mut buf);
+    let power: Vec<f32> = buf[..n / 2].iter().map(|c| c.norm_sqr()).collect();
+    let mut formants =
+        benihora::formant::find_formants_in_spectrum(&power, sample_rate / n as f32, 6000.0);
+    formants.truncate(5);
+    formants
+}
+
 pub fn benihora_tract_frequency_response(benihora: &benihora::Benihora) -> (Vec<f32>, f32) {
     let frequency = 1000.0f32;
     let res = (frequency * 2.0).log2().ceil().exp2() as usize;
@@ -281,3 +406,33 @@ pub fn benihora_tract_frequency_response(benihora: &benihora::Benihora) -> (Vec<
         .collect::<Vec<_>>();
     (buf, sample_rate / 2.0)
 }
+
+#[test]
+fn test_target_formants() {
+    let mut benihora = benihora::Benihora::new(
+        3.0,
+        48000.0,
+        1.0,
+        0,
+        false,
+        &benihora::tract::TractGeometry::default(),
+    );
+    benihora.tract.source.tongue = (22.8, 2.05);
+    benihora.tract.update_diameter();
+    let formants = target_formants(&benihora);
+
+    // Same as the Goertzel sweep of the settled tract
+    let tract = &mut benihora.tract;
+    tract.current_diameter = tract.target_diameter.clone();
+    tract.update_block(0.0);
+    let expected = benihora::formant::tract_formants(&benihora);
+    assert_eq!(formants.len(), expected.len());
+    for (formant, expected) in formants.iter().zip(&expected) {
+        assert!(
+            (formant.frequency - expected.frequency).abs() < 5.0,
+            "{:?} {:?}",
+            formants,
+            expected
+        );
+    }
+}
@@ -1,11 +1,25 @@
 #[derive(Default)]
 pub struct VoiceManager {
     voices: Vec<u8>,
+    slots: Vec<Slot>,
+    clock: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Slot {
+    pub note: Option<u8>,
+    pub velocity: f32,
+    /// Time of the last note on or note off
+    changed_at: u64,
 }
 
 impl VoiceManager {
     pub fn new() -> Self {
-        Self { voices: Vec::new() }
+        Self {
+            voices: Vec::new(),
+            slots: Vec::new(),
+            clock: 0,
+        }
     }
 
     pub fn get_voice(&mut self) -> Option<u8> {
@@ -19,4 +33,103 @@ impl VoiceManager {
     pub fn noteoff(&mut self, note: u8) {
         self.voices.retain(|&n| n != note)
     }
+
+    pub fn slot(&self, index: usize) -> Option<&Slot> {
+        self.slots.get(index)
+    }
+
+    /// Returns the index of the slot playing the note.
+    pub fn find(&self, note: u8) -> Option<usize> {
+        self.slots.iter().position(|s| s.note == Some(note))
+    }
+
+    /// Assigns the note to one of `polyphony` slots and returns the slot index.
+    /// A slot already playing the same note is reused, then the longest released slot,
+    /// and if every slot is busy the oldest note is stolen.
+    pub fn allocate(&mut self, note: u8, velocity: f32, polyphony: usize) -> usize {
+        self.slots.resize(polyphony.max(1), Slot::default());
+        self.noteoff(note);
+        self.noteon(note);
+        self.clock += 1;
+
+        let index = self
+            .slots
+            .iter()
+            .position(|s| s.note == Some(note))
+            .or_else(|| {
+                self.slots
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, s)| s.note.is_none())
+                    .min_by_key(|(_, s)| s.changed_at)
+                    .map(|(i, _)| i)
+            })
+            .unwrap_or_else(|| {
+                let (i, stolen) = self
+                    .slots
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, s)| s.changed_at)
+                    .unwrap();
+                let stolen = stolen.note.unwrap();
+                self.noteoff(stolen);
+                i
+            });
+
+        self.slots[index] = Slot {
+            note: Some(note),
+            velocity,
+            changed_at: self.clock,
+        };
+        index
+    }
+
+    /// Releases the slot playing the note and returns its index.
+    pub fn release(&mut self, note: u8) -> Option<usize> {
+        self.noteoff(note);
+        self.clock += 1;
+        let index = self.find(note)?;
+        self.slots[index].note = None;
+        self.slots[index].changed_at = self.clock;
+        Some(index)
+    }
+}
+
+#[test]
+fn test_allocate() {
+    let mut manager = VoiceManager::new();
+    assert_eq!(manager.allocate(60, 1.0, 3), 0);
+    assert_eq!(manager.allocate(64, 1.0, 3), 1);
+    // The same note takes its slot again
+    assert_eq!(manager.allocate(60, 0.5, 3), 0);
+    assert_eq!(manager.slot(0).unwrap().velocity, 0.5);
+    assert_eq!(manager.allocate(67, 1.0, 3), 2);
+
+    // The slot released the longest ago is reused first
+    assert_eq!(manager.release(64), Some(1));
+    assert_eq!(manager.release(60), Some(0));
+    assert_eq!(manager.release(60), None);
+    assert_eq!(manager.find(64), None);
+    assert_eq!(manager.allocate(72, 1.0, 3), 1);
+    assert_eq!(manager.allocate(74, 1.0, 3), 0);
+
+    // The oldest note is stolen when every slot is busy
+    assert_eq!(manager.allocate(76, 1.0, 3), 2);
+    assert_eq!(manager.find(67), None);
+    assert_eq!(manager.get_voice(), Some(76));
+    assert!(!manager.voices.contains(&67));
+}
+
+#[test]
+fn test_last_note() {
+    let mut manager = VoiceManager::new();
+    assert_eq!(manager.get_voice(), None);
+    manager.noteon(60);
+    manager.noteon(64);
+    assert_eq!(manager.get_voice(), Some(64));
+    // Releasing the last note goes back to the held one
+    manager.noteoff(64);
+    assert_eq!(manager.get_voice(), Some(60));
+    manager.noteoff(60);
+    assert_eq!(manager.get_voice(), None);
 }
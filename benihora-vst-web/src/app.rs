@@ -129,6 +129,7 @@ impl App {
         #[cfg(target_arch = "wasm32")]
         {
             let event_queue = this.event_queue.clone();
+            let state = this.state.clone();
             this.midi
                 .lock()
                 .unwrap()
@@ -145,6 +146,36 @@ impl App {
                             .unwrap()
                             .push_back(synth::Event::NoteOff { note: *nn });
                     }
+                    [160, nn, pressure] => {
+                        event_queue
+                            .lock()
+                            .unwrap()
+                            .push_back(synth::Event::PolyPressure {
+                                note: *nn,
+                                pressure: *pressure as f32 / 127.0,
+                            });
+                    }
+                    [176, cc, value] => {
+                        event_queue
+                            .lock()
+                            .unwrap()
+                            .push_back(synth::Event::ControlChange {
+                                cc: *cc,
+                                value: *value as f32 / 127.0,
+                            });
+                    }
+                    // Loading a preset allocates, so it is done here instead of in the audio callback
+                    [192, program] => {
+                        state.lock().unwrap().synth.load_preset(*program as usize);
+                    }
+                    [208, pressure] => {
+                        event_queue
+                            .lock()
+                            .unwrap()
+                            .push_back(synth::Event::ChannelPressure {
+                                pressure: *pressure as f32 / 127.0,
+                            });
+                    }
                     _ => {}
                 });
         }
@@ -189,10 +220,14 @@ impl App {
                         state.synth.benihora_params.tenseness_wobble_amount =
                             state.tenseness_wobble.smoothed_next();
                         if state.synth.tongue_control == synth::Control::Host {
-                            state.synth.benihora.as_mut().unwrap().tract.tongue_target.0 =
-                                state.tongue_x.smoothed_next();
-                            state.synth.benihora.as_mut().unwrap().tract.tongue_target.1 =
-                                state.tongue_y.smoothed_next();
+                            let tongue_x = state.tongue_x.smoothed_next();
+                            let tongue_y = state.tongue_y.smoothed_next();
+                            // The parameter is in the default tract
+                            let benihora = state.synth.benihora.as_mut().unwrap();
+                            benihora.tract.tongue_target = (
+                                benihora.benihora.tract.source.scale_index(tongue_x),
+                                tongue_y,
+                            );
                         }
                         let gain = state.gain.smoothed_next();
 
@@ -7,6 +7,8 @@ use std::sync::{Arc, Mutex};
 
 struct MyPlugin {
     params: Arc<MyPluginParams>,
+    /// The layout has the main input for the talkbox and follower modes
+    has_input: bool,
 }
 
 #[derive(Params)]
@@ -33,10 +35,17 @@ struct MyPluginParams {
     pub synth: Arc<Mutex<synth::Synth>>,
 }
 
+/// Work done off the audio thread
+enum Task {
+    /// Applies the preset of the bank on a MIDI program change
+    LoadPreset(u8),
+}
+
 impl Default for MyPlugin {
     fn default() -> Self {
         Self {
             params: Arc::new(MyPluginParams::default()),
+            has_input: false,
         }
     }
 }
@@ -104,6 +113,11 @@ impl Default for MyPluginParams {
     }
 }
 
+const MONO: NonZeroU32 = match NonZeroU32::new(1) {
+    Some(n) => n,
+    None => unreachable!(),
+};
+
 impl Plugin for MyPlugin {
     const NAME: &'static str = "Benihora";
     const VENDOR: &'static str = "carrotflakes";
@@ -112,15 +126,49 @@ impl Plugin for MyPlugin {
 
     const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-        main_input_channels: NonZeroU32::new(0),
-        main_output_channels: NonZeroU32::new(1),
-
-        aux_input_ports: &[],
-        aux_output_ports: &[],
-
-        names: PortNames::const_default(),
-    }];
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(0),
+            main_output_channels: NonZeroU32::new(1),
+
+            aux_input_ports: &[],
+            aux_output_ports: &[],
+
+            names: PortNames::const_default(),
+        },
+        // Separate buses for mixing
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(0),
+            main_output_channels: NonZeroU32::new(1),
+
+            aux_input_ports: &[],
+            aux_output_ports: &[MONO; 4],
+
+            names: PortNames {
+                layout: Some("Buses"),
+                main_input: None,
+                main_output: Some("Voice"),
+                aux_inputs: &[],
+                aux_outputs: &["Mouth", "Nose", "Glottis", "Aspiration"],
+            },
+        },
+        // Audio input for the talkbox and follower modes
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(1),
+            main_output_channels: NonZeroU32::new(1),
+
+            aux_input_ports: &[],
+            aux_output_ports: &[],
+
+            names: PortNames {
+                layout: Some("Effect"),
+                main_input: Some("Input"),
+                main_output: Some("Voice"),
+                aux_inputs: &[],
+                aux_outputs: &[],
+            },
+        },
+    ];
 
     const MIDI_INPUT: MidiConfig = MidiConfig::MidiCCs;
     const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
@@ -128,12 +176,19 @@ impl Plugin for MyPlugin {
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
 
     type SysExMessage = ();
-    type BackgroundTask = ();
+    type BackgroundTask = Task;
 
     fn params(&self) -> Arc<dyn Params> {
         self.params.clone()
     }
 
+    fn task_executor(&mut self) -> TaskExecutor<Self> {
+        let params = self.params.clone();
+        Box::new(move |task| match task {
+            Task::LoadPreset(program) => params.synth.lock().unwrap().load_preset(program as usize),
+        })
+    }
+
     fn editor(&mut self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
         create_egui_editor(
             self.params.editor_state.clone(),
@@ -165,13 +220,14 @@ impl Plugin for MyPlugin {
 
     fn initialize(
         &mut self,
-        _audio_io_layout: &AudioIOLayout,
+        audio_io_layout: &AudioIOLayout,
         _buffer_config: &BufferConfig,
         _context: &mut impl InitContext<Self>,
     ) -> bool {
         // Resize buffers and perform other potentially expensive initialization operations here.
         // The `reset()` function is always called right after this function. You can remove this
         // function if you do not need it.
+        self.has_input = audio_io_layout.main_input_channels.is_some();
         true
     }
 
@@ -183,19 +239,26 @@ impl Plugin for MyPlugin {
     fn process(
         &mut self,
         buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
+        aux: &mut AuxiliaryBuffers,
         context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
         let mut synth = self.params.synth.lock().unwrap();
 
-        let sample_rate = context.transport().sample_rate;
+        let transport = context.transport();
+        let sample_rate = transport.sample_rate;
         synth.ensure_benihora(sample_rate);
+        let position = if transport.playing {
+            transport.pos_beats()
+        } else {
+            None
+        };
+        synth.set_transport(transport.tempo.unwrap_or(120.0) as f32, position);
 
         let mut count = 0;
         let mut event = context.next_event();
         let dtime = 1.0 / sample_rate;
 
-        for mut channel_samples in buffer.iter_samples() {
+        for (i, mut channel_samples) in buffer.iter_samples().enumerate() {
             synth.benihora_params.vibrato_amount = self.params.vibrato_amount.smoothed.next();
             synth.benihora_params.vibrato_rate = self.params.vibrato_rate.smoothed.next();
             synth.benihora_params.frequency_wobble_amount =
@@ -203,16 +266,22 @@ impl Plugin for MyPlugin {
             synth.benihora_params.tenseness_wobble_amount =
                 self.params.tenseness_wobble.smoothed.next();
             if synth.tongue_control == synth::Control::Host {
-                synth.benihora.as_mut().unwrap().tract.tongue_target.0 =
-                    self.params.tongue_x.smoothed.next();
-                synth.benihora.as_mut().unwrap().tract.tongue_target.1 =
-                    self.params.tongue_y.smoothed.next();
+                // The parameter is in the default tract
+                let benihora = synth.benihora.as_mut().unwrap();
+                benihora.tract.tongue_target.0 = benihora
+                    .benihora
+                    .tract
+                    .source
+                    .scale_index(self.params.tongue_x.smoothed.next());
+                benihora.tract.tongue_target.1 = self.params.tongue_y.smoothed.next();
             }
             let gain = self.params.gain.smoothed.next();
 
             while let Some(e) = event {
                 if e.timing() <= count {
-                    if let Some(e) = convert_event(&e) {
+                    if let NoteEvent::MidiProgramChange { program, .. } = e {
+                        context.execute_background(Task::LoadPreset(program));
+                    } else if let Some(e) = convert_event(&e) {
                         synth.handle_event(&e);
                     }
                     event = context.next_event();
@@ -222,7 +291,27 @@ impl Plugin for MyPlugin {
             }
             count += 1;
 
-            *channel_samples.get_mut(0).unwrap() = synth.process(dtime) * gain;
+            // The input and the output share the buffer
+            let input = if self.has_input {
+                *channel_samples.get_mut(0).unwrap()
+            } else {
+                0.0
+            };
+
+            if aux.outputs.is_empty() {
+                *channel_samples.get_mut(0).unwrap() = synth.process_input(dtime, input) * gain;
+            } else {
+                let buses = synth.process_buses_input(dtime, input);
+                *channel_samples.get_mut(0).unwrap() = buses.output() * gain;
+                for (output, y) in aux.outputs.iter_mut().zip([
+                    buses.mouth,
+                    buses.nose,
+                    buses.glottis,
+                    buses.aspiration,
+                ]) {
+                    output.as_slice()[0][i] = y * gain;
+                }
+            }
         }
 
         ProcessStatus::Normal
@@ -294,13 +383,20 @@ fn convert_event(event: &NoteEvent<()>) -> Option<synth::Event> {
             velocity: *velocity,
         }),
         NoteEvent::NoteOff { note, velocity, .. } => Some(synth::Event::NoteOff { note: *note }),
-        NoteEvent::PolyPressure { note, pressure, .. } => None, // = aftertouch
-        NoteEvent::MidiChannelPressure { pressure, .. } => None, // = channel aftertouch
+        NoteEvent::PolyPressure { note, pressure, .. } => Some(synth::Event::PolyPressure {
+            note: *note,
+            pressure: *pressure,
+        }),
+        NoteEvent::MidiChannelPressure { pressure, .. } => Some(synth::Event::ChannelPressure {
+            pressure: *pressure,
+        }),
         NoteEvent::MidiPitchBend { value, .. } => Some(synth::Event::PitchBend {
             value: (value * 2.0 - 1.0) / 12.0,
         }),
-        NoteEvent::MidiCC { cc, value, .. } => None,
-        NoteEvent::MidiProgramChange { program, .. } => None,
+        NoteEvent::MidiCC { cc, value, .. } => Some(synth::Event::ControlChange {
+            cc: *cc,
+            value: *value,
+        }),
         _ => None,
     }
 }
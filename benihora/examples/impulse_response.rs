@@ -3,10 +3,10 @@
 
 use std::io::Write;
 
-use benihora::{tract_impulse_response, Benihora};
+use benihora::{tract::TractGeometry, tract_impulse_response, Benihora};
 
 fn main() {
-    let benihora = Benihora::new(3.0, 48000.0, 1.0, 0, false);
+    let benihora = Benihora::new(3.0, 48000.0, 1.0, 0, false, &TractGeometry::default());
     let buf = tract_impulse_response(48000, &benihora).0;
 
     let mut stdout = std::io::stdout();
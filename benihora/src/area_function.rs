@@ -0,0 +1,245 @@
+//! Measured vocal-tract area functions (e.g. from MRI).
+//!
+//! The text format has one `position area` pair per line, where position is the distance
+//! from the glottis in cm and area is the cross-sectional area in cm^2.
+//! Positions must be increasing. Empty lines and text after `#` are ignored.
+//!
+//! ```text
+//! # /a/
+//! 0.0  0.45
+//! 0.5  0.20
+//! ...
+//! 17.0 4.10
+//! ```
+
+use crate::tract::ShapeSource;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AreaFunction {
+    /// cm from the glottis
+    pub positions: Vec<f32>,
+    /// cm^2
+    pub areas: Vec<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// 1-based line number
+    InvalidLine(usize),
+    /// 1-based line number
+    NotIncreasing(usize),
+    TooShort,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::InvalidLine(line) => write!(f, "invalid line: {}", line),
+            ParseError::NotIncreasing(line) => {
+                write!(f, "position is not increasing at line {}", line)
+            }
+            ParseError::TooShort => write!(f, "at least two points are required"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl AreaFunction {
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut positions = Vec::new();
+        let mut areas = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values: Vec<f32> = line
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse().ok().filter(|x: &f32| x.is_finite()))
+                .collect::<Option<_>>()
+                .ok_or(ParseError::InvalidLine(i + 1))?;
+            let [position, area] = values[..] else {
+                return Err(ParseError::InvalidLine(i + 1));
+            };
+            if area < 0.0 {
+                return Err(ParseError::InvalidLine(i + 1));
+            }
+            if positions.last().is_some_and(|&p| p >= position) {
+                return Err(ParseError::NotIncreasing(i + 1));
+            }
+            positions.push(position);
+            areas.push(area);
+        }
+
+        if positions.len() < 2 {
+            return Err(ParseError::TooShort);
+        }
+        Ok(Self { positions, areas })
+    }
+
+    /// Reads a file of the text format.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Area function of equally spaced sections, from the glottis to the lips.
+    pub fn from_sections(areas: Vec<f32>, section_length: f32) -> Self {
+        assert!(areas.len() >= 2);
+        Self {
+            positions: (0..areas.len())
+                .map(|i| (i as f32 + 0.5) * section_length)
+                .collect(),
+            areas,
+        }
+    }
+
+    pub fn area_at(&self, position: f32) -> f32 {
+        let i = self.positions.partition_point(|&p| p <= position);
+        if i == 0 {
+            return self.areas[0];
+        }
+        if i == self.positions.len() {
+            return self.areas[i - 1];
+        }
+        let t = (position - self.positions[i - 1]) / (self.positions[i] - self.positions[i - 1]);
+        crate::lerp(self.areas[i - 1], self.areas[i], t)
+    }
+
+    /// Resamples the area function onto `length` segments spanning from the glottis to the lips
+    /// and returns the diameters of the circles of the areas.
+    pub fn resample(&self, length: usize) -> Vec<f32> {
+        let start = self.positions[0];
+        let end = *self.positions.last().unwrap();
+        (0..length)
+            .map(|i| {
+                let position = start + (end - start) * i as f32 / (length - 1) as f32;
+                diameter(self.area_at(position))
+            })
+            .collect()
+    }
+
+    /// Makes this the mouth shape of `source` in place of the rest shape and the tongue.
+    /// It takes effect at the next `Tract::update_diameter`.
+    /// Set `ShapeSource::measured_diameter` to None to go back to the tongue.
+    pub fn apply(&self, source: &mut ShapeSource) {
+        source.measured_diameter = Some(self.resample(source.length));
+    }
+}
+
+/// Diameter in cm of the circle of `area` in cm^2
+fn diameter(area: f32) -> f32 {
+    (4.0 * area / std::f32::consts::PI).sqrt()
+}
+
+/// Keyframes of area functions interpolated over time.
+#[derive(Debug, Clone, Default)]
+pub struct AreaSequence {
+    keyframes: Vec<(f32, AreaFunction)>,
+}
+
+impl AreaSequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keyframes must be pushed in increasing time order.
+    pub fn push(&mut self, time: f32, area_function: AreaFunction) {
+        assert!(self.keyframes.last().is_none_or(|k| k.0 < time));
+        self.keyframes.push((time, area_function));
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.0)
+    }
+
+    /// Makes the shape at `time` the mouth shape of `source` like `AreaFunction::apply`.
+    /// Areas are interpolated linearly between the surrounding keyframes.
+    pub fn apply(&self, time: f32, source: &mut ShapeSource) {
+        let i = self.keyframes.partition_point(|k| k.0 <= time);
+        if i == 0 || i == self.keyframes.len() {
+            if let Some((_, area_function)) = self.keyframes.get(i.saturating_sub(1)) {
+                area_function.apply(source);
+            }
+            return;
+        }
+
+        let (t0, a0) = &self.keyframes[i - 1];
+        let (t1, a1) = &self.keyframes[i];
+        let t = (time - t0) / (t1 - t0);
+        let d0 = a0.resample(source.length);
+        let d1 = a1.resample(source.length);
+        source.measured_diameter = Some(
+            d0.iter()
+                .zip(d1.iter())
+                // The area goes linearly, which is the square of the diameter
+                .map(|(d0, d1)| crate::lerp(d0 * d0, d1 * d1, t).sqrt())
+                .collect(),
+        );
+    }
+}
+
+#[test]
+fn test() {
+    use crate::tract::{Tract, TractGeometry};
+
+    let a = AreaFunction::parse("# comment\n0.0 0.25\n\n8.0 4.0 # middle\n16.0 1.0\n").unwrap();
+    assert_eq!(a.area_at(4.0), 2.125);
+    assert_eq!(
+        AreaFunction::parse("0 1\n0 2"),
+        Err(ParseError::NotIncreasing(2))
+    );
+    assert_eq!(
+        AreaFunction::parse("0 1 2"),
+        Err(ParseError::InvalidLine(1))
+    );
+
+    // The shape stays over the tongue and under the constrictions
+    let mut tract = Tract::new(2, 48000.0, 0, &TractGeometry::default());
+    a.apply(&mut tract.source);
+    tract.source.tongue = (20.0, 2.0);
+    tract.source.other_constrictions = vec![(30.0, 0.5)];
+    tract.update_diameter();
+    let mouth = &tract.target_diameter.mouth;
+    assert_eq!(mouth[0], diameter(0.25));
+    assert_eq!(mouth[43], diameter(1.0));
+    assert!((diameter(std::f32::consts::PI) - 2.0).abs() < 1e-6);
+    assert_eq!(mouth[20], a.resample(44)[20]);
+    assert!(mouth[30] < 0.5);
+
+    let b = AreaFunction::from_sections(vec![1.0; 10], 1.7);
+    let mut sequence = AreaSequence::new();
+    sequence.push(0.0, a);
+    sequence.push(1.0, b);
+    let source = &mut tract.source;
+    sequence.apply(0.5, source);
+    let measured = source.measured_diameter.as_ref().unwrap();
+    assert!((measured[0] - diameter(0.625)).abs() < 1e-6);
+    sequence.apply(2.0, source);
+    assert!(source
+        .measured_diameter
+        .as_ref()
+        .unwrap()
+        .iter()
+        .all(|&d| d == diameter(1.0)));
+
+    // Back to the tongue
+    source.measured_diameter = None;
+    tract.update_diameter();
+    assert_ne!(tract.target_diameter.mouth[20], diameter(1.0));
+
+    let path = std::env::temp_dir().join(format!(
+        "benihora_area_function_test_{}.txt",
+        std::process::id()
+    ));
+    std::fs::write(&path, "0 1\n1 2\n").unwrap();
+    assert_eq!(AreaFunction::load(&path).unwrap().areas, vec![1.0, 2.0]);
+    std::fs::write(&path, "0 1\n").unwrap();
+    let error = AreaFunction::load(&path).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    std::fs::remove_file(&path).unwrap();
+}
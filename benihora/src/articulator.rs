@@ -0,0 +1,107 @@
+//! Second-order dynamics of the articulators.
+
+use std::f32::consts::TAU;
+
+/// Largest `omega * dt` of an integration step, which keeps the integration stable and accurate
+const MAX_STEP: f32 = 0.1;
+
+/// A mass-spring-damper that pulls an articulator toward its target
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Spring {
+    /// Natural frequency in Hz. Higher is faster.
+    pub frequency: f32,
+    /// Damping ratio. 1 is critically damped and lower values overshoot the target.
+    pub damping: f32,
+}
+
+impl Spring {
+    pub fn new(frequency: f32, damping: f32) -> Self {
+        Self { frequency, damping }
+    }
+
+    /// Advances `position` and `velocity` toward `target` by `dtime` seconds.
+    pub fn update(&self, position: &mut f32, velocity: &mut f32, target: f32, dtime: f32) {
+        let omega = TAU * self.frequency;
+        let steps = (omega * dtime / MAX_STEP).ceil().max(1.0);
+        let dt = dtime / steps;
+        for _ in 0..steps as usize {
+            // Semi-implicit Euler
+            let acceleration =
+                omega * omega * (target - *position) - 2.0 * self.damping * omega * *velocity;
+            *velocity += acceleration * dt;
+            *position += *velocity * dt;
+        }
+    }
+}
+
+/// Springs of the parts of the tract
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Articulators {
+    /// The pharynx and the tongue body
+    pub tongue: Spring,
+    /// From the tongue tip to the lips
+    pub tip: Spring,
+    pub lips: Spring,
+    pub velum: Spring,
+}
+
+impl Articulators {
+    /// Rough speeds of the articulators in speech, the tip and the lips being the fastest.
+    /// They overshoot the target slightly.
+    pub fn natural() -> Self {
+        Self {
+            tongue: Spring::new(7.0, 0.8),
+            tip: Spring::new(12.0, 0.8),
+            lips: Spring::new(10.0, 0.8),
+            velum: Spring::new(5.0, 1.0),
+        }
+    }
+
+    /// Multiplies the frequencies of all the springs by `speed` and the damping ratios by `damping`.
+    pub fn scaled(mut self, speed: f32, damping: f32) -> Self {
+        for spring in [
+            &mut self.tongue,
+            &mut self.tip,
+            &mut self.lips,
+            &mut self.velum,
+        ] {
+            spring.frequency *= speed;
+            spring.damping *= damping;
+        }
+        self
+    }
+}
+
+#[test]
+fn test_spring() {
+    let dtime = 0.001;
+    let simulate = |spring: Spring| {
+        let (mut position, mut velocity) = (0.0, 0.0);
+        (0..1000)
+            .map(|_| {
+                spring.update(&mut position, &mut velocity, 1.0, dtime);
+                position
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Critically damped: no overshoot, settled in a few periods
+    let positions = simulate(Spring::new(10.0, 1.0));
+    assert!(positions.iter().all(|&x| x <= 1.0));
+    assert!(positions[500] > 0.999);
+    // It starts slowly instead of jumping toward the target
+    assert!(positions[2] < 0.05);
+
+    // Underdamped: overshoots
+    let positions = simulate(Spring::new(10.0, 0.5));
+    assert!(positions.iter().any(|&x| x > 1.1));
+    assert!((positions[999] - 1.0).abs() < 0.01);
+
+    // A long step is as stable as the short ones
+    let spring = Spring::new(10.0, 1.0);
+    let (mut position, mut velocity) = (0.0, 0.0);
+    spring.update(&mut position, &mut velocity, 1.0, 1.0);
+    assert!((position - positions[999]).abs() < 0.01);
+}
@@ -1,8 +1,13 @@
-use crate::resample::Resample;
+use std::collections::VecDeque;
 
-use super::glottis::Glottis;
-use super::tract::Tract;
+use crate::glottal_source::GlottalModel;
+use crate::resample::{Frame, Resample};
 
+use super::glottis::{Glottis, VoiceQuality};
+use super::tract::{Tract, TractGeometry};
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Benihora {
     force_turbulence: bool,
     pub sample_rate: f32,
@@ -10,9 +15,75 @@ pub struct Benihora {
     pub glottis: Glottis,
     pub tract: Tract,
     resample: Resample,
+    bus_resample: Resample<Buses>,
     glottal_output: f32,
+    /// Input samples of `process_excitation` waiting for the inner sample rate
+    excitation: VecDeque<f32>,
+    excitation_resample: Resample,
+}
+
+/// Output of `Benihora::process_buses`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Buses {
+    /// Radiated from the lips
+    pub mouth: f32,
+    /// Radiated from the nostrils
+    pub nose: f32,
+    /// Voiced glottal flow derivative before the tract
+    pub glottis: f32,
+    /// Aspiration noise before the tract
+    pub aspiration: f32,
+}
+
+impl Buses {
+    /// The output of the tract, equivalent to `Benihora::process`
+    pub fn output(&self) -> f32 {
+        self.mouth + self.nose
+    }
+}
+
+impl std::ops::Add for Buses {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            mouth: self.mouth + rhs.mouth,
+            nose: self.nose + rhs.nose,
+            glottis: self.glottis + rhs.glottis,
+            aspiration: self.aspiration + rhs.aspiration,
+        }
+    }
 }
 
+impl std::ops::Sub for Buses {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self {
+            mouth: self.mouth - rhs.mouth,
+            nose: self.nose - rhs.nose,
+            glottis: self.glottis - rhs.glottis,
+            aspiration: self.aspiration - rhs.aspiration,
+        }
+    }
+}
+
+impl std::ops::Mul<f32> for Buses {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self {
+        Self {
+            mouth: self.mouth * rhs,
+            nose: self.nose * rhs,
+            glottis: self.glottis * rhs,
+            aspiration: self.aspiration * rhs,
+        }
+    }
+}
+
+impl Frame for Buses {}
+
 impl Benihora {
     pub fn new(
         sound_speed: f32,
@@ -20,6 +91,28 @@ impl Benihora {
         over_sample: f32,
         seed: u32,
         force_turbulence: bool,
+        geometry: &TractGeometry,
+    ) -> Self {
+        Self::with_glottal_source(
+            sound_speed,
+            sample_rate,
+            over_sample,
+            seed,
+            force_turbulence,
+            geometry,
+            GlottalModel::default(),
+        )
+    }
+
+    /// Same as `new` but the glottis is driven by `source` instead of the Liljencrants-Fant model.
+    pub fn with_glottal_source(
+        sound_speed: f32,
+        sample_rate: f32,
+        over_sample: f32,
+        seed: u32,
+        force_turbulence: bool,
+        geometry: &TractGeometry,
+        source: impl Into<GlottalModel>,
     ) -> Self {
         assert!(seed < u32::MAX - 2);
 
@@ -31,10 +124,18 @@ impl Benihora {
             force_turbulence,
             sample_rate,
             inner_sample_rate,
-            glottis: Glottis::new(inner_sample_rate, seed),
-            tract: Tract::new(tract_steps_per_process, inner_sample_rate, seed + 1),
+            glottis: Glottis::with_source(inner_sample_rate, seed, source.into()),
+            tract: Tract::new(
+                tract_steps_per_process,
+                inner_sample_rate,
+                seed + 1,
+                geometry,
+            ),
             resample: Resample::new(inner_sample_rate, sample_rate),
+            bus_resample: Resample::new(inner_sample_rate, sample_rate),
             glottal_output: 0.0,
+            excitation: VecDeque::new(),
+            excitation_resample: Resample::new(sample_rate, inner_sample_rate),
         }
     }
 
@@ -42,6 +143,31 @@ impl Benihora {
         self.glottal_output
     }
 
+    /// Replaces the default resampler with a band-limited windowed-sinc resampler.
+    /// See `Resample::new_sinc` for `zero_crossings`.
+    pub fn use_sinc_resample(&mut self, zero_crossings: usize) {
+        self.resample =
+            Resample::new_sinc(self.inner_sample_rate, self.sample_rate, zero_crossings);
+        self.bus_resample =
+            Resample::new_sinc(self.inner_sample_rate, self.sample_rate, zero_crossings);
+        self.excitation_resample =
+            Resample::new_sinc(self.sample_rate, self.inner_sample_rate, zero_crossings);
+    }
+
+    /// Replaces the Liljencrants-Fant glottal source with another model.
+    pub fn use_glottal_source(&mut self, source: impl Into<GlottalModel>) {
+        self.glottis.source = source.into();
+    }
+
+    pub fn set_voice_quality(&mut self, voice_quality: VoiceQuality) {
+        self.glottis.voice_quality = voice_quality;
+    }
+
+    /// Delay of the output in samples caused by resampling
+    pub fn latency(&self) -> f32 {
+        self.resample.latency()
+    }
+
     pub fn process(
         &mut self,
         frequency: f32,
@@ -59,6 +185,7 @@ impl Benihora {
         } else {
             intensity
         };
+        self.tract.params.update_tenseness(tenseness);
 
         self.resample.process(|| {
             self.glottal_output =
@@ -68,4 +195,241 @@ impl Benihora {
             self.tract.process(tract_intensity, self.glottal_output)
         })
     }
+
+    /// Same as `process` but returns the mouth, nose, and glottal source signals separately.
+    /// The four buses are resampled apart from the mono output so that `process` costs
+    /// a quarter as much. Each has its own resampler history, so stick to one of them on an instance.
+    pub fn process_buses(
+        &mut self,
+        frequency: f32,
+        tenseness: f32,
+        intensity: f32,
+        loudness: f32,
+        aspiration_level: f32,
+    ) -> Buses {
+        debug_assert!((1.0..=10000.0).contains(&frequency));
+        debug_assert!((0.0..=1.0).contains(&tenseness));
+        debug_assert!((0.0..=1.0).contains(&loudness));
+
+        let tract_intensity = if self.force_turbulence {
+            1.0
+        } else {
+            intensity
+        };
+        self.tract.params.update_tenseness(tenseness);
+
+        self.bus_resample.process(|| {
+            let (glottis, aspiration) = self.glottis.process_split(
+                frequency,
+                tenseness,
+                intensity,
+                loudness,
+                aspiration_level,
+            );
+            self.glottal_output = glottis + aspiration;
+
+            let (mouth, nose) = self
+                .tract
+                .process_split(tract_intensity, self.glottal_output);
+            Buses {
+                mouth,
+                nose,
+                glottis,
+                aspiration,
+            }
+        })
+    }
+
+    /// Same as `process` but the tract is excited by `excitation` instead of the glottis,
+    /// e.g. to shape an external synth like a talkbox.
+    /// `intensity` only controls the turbulence noise of the tract.
+    pub fn process_excitation(&mut self, excitation: f32, intensity: f32) -> f32 {
+        let tract_intensity = if self.force_turbulence {
+            1.0
+        } else {
+            intensity
+        };
+        self.push_excitation(excitation);
+
+        self.resample.process(|| {
+            self.glottal_output = self
+                .excitation_resample
+                .process(|| self.excitation.pop_front().unwrap_or_default());
+
+            self.tract.process(tract_intensity, self.glottal_output)
+        })
+    }
+
+    /// Same as `process_excitation` but returns the buses like `process_buses`.
+    /// The excitation is on the glottis bus.
+    pub fn process_excitation_buses(&mut self, excitation: f32, intensity: f32) -> Buses {
+        let tract_intensity = if self.force_turbulence {
+            1.0
+        } else {
+            intensity
+        };
+        self.push_excitation(excitation);
+
+        self.bus_resample.process(|| {
+            self.glottal_output = self
+                .excitation_resample
+                .process(|| self.excitation.pop_front().unwrap_or_default());
+
+            let (mouth, nose) = self
+                .tract
+                .process_split(tract_intensity, self.glottal_output);
+            Buses {
+                mouth,
+                nose,
+                glottis: self.glottal_output,
+                aspiration: 0.0,
+            }
+        })
+    }
+
+    fn push_excitation(&mut self, excitation: f32) {
+        // The inner sample rate consumes the input unevenly, so a few samples are buffered.
+        // Drop the oldest ones if the buffer grows.
+        if self.excitation.len() >= 4 {
+            self.excitation.pop_front();
+        }
+        self.excitation.push_back(excitation);
+    }
+
+    /// Fills `output` by calling `process` for each sample.
+    /// Each parameter slice must have either one value, which is used for the whole block,
+    /// or one value per output sample.
+    pub fn process_block(
+        &mut self,
+        output: &mut [f32],
+        frequency: &[f32],
+        tenseness: &[f32],
+        intensity: &[f32],
+        loudness: &[f32],
+        aspiration_level: &[f32],
+    ) {
+        for values in [frequency, tenseness, intensity, loudness, aspiration_level] {
+            assert!(
+                values.len() == 1 || values.len() == output.len(),
+                "parameter length {} doesn't match the block length {}",
+                values.len(),
+                output.len()
+            );
+        }
+        for (i, y) in output.iter_mut().enumerate() {
+            *y = self.process(
+                block_param(frequency, i),
+                block_param(tenseness, i),
+                block_param(intensity, i),
+                block_param(loudness, i),
+                block_param(aspiration_level, i),
+            );
+        }
+    }
+}
+
+#[inline]
+fn block_param(values: &[f32], i: usize) -> f32 {
+    if values.len() == 1 {
+        values[0]
+    } else {
+        values[i]
+    }
+}
+
+#[test]
+fn test_process_block() {
+    let mut benihora1 = Benihora::new(3.0, 44100.0, 1.0, 0, false, &TractGeometry::default());
+    let mut benihora2 = Benihora::new(3.0, 44100.0, 1.0, 0, false, &TractGeometry::default());
+
+    let n = 4410;
+    let frequency: Vec<_> = (0..n).map(|i| 100.0 + i as f32 * 0.05).collect();
+    let intensity: Vec<_> = (0..n).map(|i| (i as f32 / 1000.0).min(1.0)).collect();
+
+    let expected: Vec<_> = (0..n)
+        .map(|i| benihora1.process(frequency[i], 0.6, intensity[i], 0.8, 1.0))
+        .collect();
+
+    let mut output = vec![0.0; n];
+    for range in [0..1000, 1000..1001, 1001..n] {
+        benihora2.process_block(
+            &mut output[range.clone()],
+            &frequency[range.clone()],
+            &[0.6],
+            &intensity[range.clone()],
+            &[0.8],
+            &[1.0],
+        );
+    }
+
+    assert!(expected
+        .iter()
+        .zip(output.iter())
+        .all(|(a, b)| a.to_bits() == b.to_bits()));
+}
+
+#[test]
+#[should_panic(expected = "doesn't match the block length")]
+fn test_process_block_length() {
+    let mut benihora = Benihora::new(3.0, 44100.0, 1.0, 0, false, &TractGeometry::default());
+    let mut output = [0.0; 8];
+    benihora.process_block(&mut output, &[140.0], &[0.6; 4], &[1.0], &[0.9], &[1.0]);
+}
+
+#[test]
+fn test_snapshot() {
+    let mut benihora = Benihora::new(3.0, 44100.0, 1.0, 0, false, &TractGeometry::default());
+    benihora.tract.source.other_constrictions = vec![(36.0, 0.5)];
+    benihora.tract.update_diameter();
+    for i in 0..4410 {
+        benihora.process(120.0 + (i % 100) as f32, 0.6, 0.8, 0.9, 1.0);
+    }
+
+    let mut snapshot = benihora.clone();
+    assert!(snapshot == benihora);
+
+    for i in 0..4410 {
+        let a = benihora.process(140.0, 0.6 + (i % 10) as f32 * 0.01, 0.8, 0.9, 1.0);
+        let b = snapshot.process(140.0, 0.6 + (i % 10) as f32 * 0.01, 0.8, 0.9, 1.0);
+        assert_eq!(a.to_bits(), b.to_bits());
+    }
+    assert!(snapshot == benihora);
+}
+
+#[test]
+fn test_process_buses() {
+    let mut benihora1 = Benihora::new(3.0, 44100.0, 1.0, 0, false, &TractGeometry::default());
+    let mut benihora2 = benihora1.clone();
+
+    for i in 0..4410 {
+        let frequency = 120.0 + (i % 100) as f32;
+        let y = benihora1.process(frequency, 0.6, 0.8, 0.9, 1.0);
+        let buses = benihora2.process_buses(frequency, 0.6, 0.8, 0.9, 1.0);
+        assert!((y - buses.output()).abs() < 1e-5);
+    }
+    assert_eq!(benihora1.glottis, benihora2.glottis);
+}
+
+#[test]
+fn test_process_excitation() {
+    let mut benihora1 = Benihora::new(3.0, 44100.0, 1.0, 0, false, &TractGeometry::default());
+    let mut benihora2 = benihora1.clone();
+
+    let mut energy = 0.0;
+    for i in 0..4410 {
+        let x = (i as f32 * 0.05).sin() * 0.5;
+        let y = benihora1.process_excitation(x, 0.0);
+        let buses = benihora2.process_excitation_buses(x, 0.0);
+        assert!((y - buses.output()).abs() < 1e-5);
+        assert!(buses.aspiration == 0.0);
+        energy += y * y;
+    }
+    assert!(energy > 1.0);
+    assert!(benihora1.excitation.len() <= 4);
+
+    // Silence in, silence out
+    for _ in 0..4410 {
+        benihora1.process_excitation(0.0, 0.0);
+    }
+    assert!(benihora1.process_excitation(0.0, 0.0).abs() < 1e-3);
 }
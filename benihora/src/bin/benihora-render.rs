@@ -0,0 +1,308 @@
+// Renders a phoneme score to a WAV file.
+// cargo run --release --bin benihora-render -- score.txt -o out.wav --sample-rate 44100 --bits 24
+//
+// The score uses the format of `benihora::phoneme`. `#` starts a comment.
+
+use std::io::{Read, Write};
+
+use benihora::{
+    area_function::AreaFunction,
+    glottal_source::{GlottalModel, Klglott88, Rosenberg, TwoMass},
+    glottis::LiljencrantsFant,
+    managed::BenihoraManaged,
+    phoneme,
+    tract::{GlottalReflection, Losses, Radiation},
+};
+
+const USAGE: &str = "\
+Usage: benihora-render [OPTIONS] <SCORE>
+
+Renders a phoneme score (`-` for stdin) to a WAV file.
+
+Options:
+  -o, --output <FILE>        Output WAV file [default: out.wav]
+  -r, --sample-rate <HZ>     Sample rate [default: 48000]
+  -b, --bits <BITS>          16 or 24 for PCM, 32 for float [default: 16]
+      --sound-speed <SPEED>  Sound speed, 2: Male, 3: Female, 4~: Child [default: 3]
+      --seed <SEED>          Seed of the wobble pattern [default: 0]
+      --over-sample <RATE>   Oversampling rate of the glottis and tract [default: 1]
+      --sinc <ZEROS>         Use a windowed-sinc resampler with the number of zero crossings
+                             (e.g. 8 or 32) instead of the linear resampler
+      --radiation <MODEL>    constant or filtered [default: constant]
+      --losses <MODEL>       uniform or physical [default: uniform]
+      --glottal-reflection <MODEL>
+                             fixed or tenseness [default: fixed]
+      --glottis <MODEL>      lf, rosenberg, klglott88 or two-mass [default: lf]
+      --area-function <FILE> Measured mouth shape used in place of the tongue
+                             (see `benihora::area_function` for the format)
+  -h, --help                 Print help";
+
+struct Options {
+    input: String,
+    output: String,
+    sample_rate: u32,
+    bits: u16,
+    sound_speed: f32,
+    seed: u32,
+    over_sample: f32,
+    sinc: Option<usize>,
+    radiation: Radiation,
+    losses: Losses,
+    glottal_reflection: GlottalReflection,
+    glottis: GlottalModel,
+    area_function: Option<String>,
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("Benihora offline renderer\n\n{}", USAGE);
+            return;
+        }
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(message) = run(&options) {
+        eprintln!("error: {}", message);
+        std::process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let mut score = String::new();
+    if options.input == "-" {
+        std::io::stdin()
+            .read_to_string(&mut score)
+            .map_err(|e| e.to_string())?;
+    } else {
+        score = std::fs::read_to_string(&options.input)
+            .map_err(|e| format!("{}: {}", options.input, e))?;
+    }
+    let score: String = score
+        .lines()
+        .map(|line| line.split('#').next().unwrap())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut benihora = BenihoraManaged::with_over_sample(
+        options.sound_speed,
+        options.sample_rate as f32,
+        options.over_sample,
+        options.seed,
+    );
+    if let Some(zero_crossings) = options.sinc {
+        benihora.benihora.use_sinc_resample(zero_crossings);
+    }
+    benihora
+        .benihora
+        .tract
+        .params
+        .set_radiation(options.radiation);
+    benihora
+        .benihora
+        .tract
+        .params
+        .set_glottal_reflection(options.glottal_reflection);
+    benihora.benihora.tract.set_losses(options.losses);
+    benihora
+        .benihora
+        .use_glottal_source(options.glottis.clone());
+    if let Some(path) = &options.area_function {
+        let area_function = AreaFunction::load(path).map_err(|e| format!("{}: {}", path, e))?;
+        area_function.apply(&mut benihora.benihora.tract.source);
+    }
+    let buffer = phoneme::render(&score, &mut benihora).map_err(|e| e.to_string())?;
+
+    let file =
+        std::fs::File::create(&options.output).map_err(|e| format!("{}: {}", options.output, e))?;
+    let mut writer = std::io::BufWriter::new(file);
+    write_wav(&mut writer, &buffer, options.sample_rate, options.bits)
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("{}: {}", options.output, e))?;
+
+    Ok(())
+}
+
+/// Returns None if the help is requested.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        input: String::new(),
+        output: "out.wav".to_owned(),
+        sample_rate: 48000,
+        bits: 16,
+        sound_speed: 3.0,
+        seed: 0,
+        over_sample: 1.0,
+        sinc: None,
+        radiation: Radiation::Constant,
+        losses: Losses::Uniform,
+        glottal_reflection: GlottalReflection::Fixed(0.75),
+        glottis: GlottalModel::default(),
+        area_function: None,
+    };
+    let mut input = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => options.output = value()?,
+            "-r" | "--sample-rate" => options.sample_rate = parse_value(&arg, &value()?)?,
+            "-b" | "--bits" => options.bits = parse_value(&arg, &value()?)?,
+            "--sound-speed" => options.sound_speed = parse_value(&arg, &value()?)?,
+            "--seed" => options.seed = parse_value(&arg, &value()?)?,
+            "--over-sample" => options.over_sample = parse_value(&arg, &value()?)?,
+            "--sinc" => options.sinc = Some(parse_value(&arg, &value()?)?),
+            "--radiation" => {
+                options.radiation = match value()?.as_str() {
+                    "constant" => Radiation::Constant,
+                    "filtered" => Radiation::filtered(),
+                    v => return Err(format!("invalid value for {}: {}", arg, v)),
+                }
+            }
+            "--losses" => {
+                options.losses = match value()?.as_str() {
+                    "uniform" => Losses::Uniform,
+                    "physical" => Losses::physical(),
+                    v => return Err(format!("invalid value for {}: {}", arg, v)),
+                }
+            }
+            "--glottal-reflection" => {
+                options.glottal_reflection = match value()?.as_str() {
+                    "fixed" => GlottalReflection::Fixed(0.75),
+                    "tenseness" => GlottalReflection::tenseness(),
+                    v => return Err(format!("invalid value for {}: {}", arg, v)),
+                }
+            }
+            "--glottis" => {
+                options.glottis = match value()?.as_str() {
+                    "lf" => LiljencrantsFant::new().into(),
+                    "rosenberg" => Rosenberg::new().into(),
+                    "klglott88" => Klglott88::new().into(),
+                    "two-mass" => TwoMass::new().into(),
+                    v => return Err(format!("invalid value for {}: {}", arg, v)),
+                }
+            }
+            "--area-function" => options.area_function = Some(value()?),
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option: {}", arg))
+            }
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("unexpected argument: {}", arg)),
+        }
+    }
+
+    options.input = input.ok_or("missing score file")?;
+    if ![16, 24, 32].contains(&options.bits) {
+        return Err(format!("unsupported bit depth: {}", options.bits));
+    }
+    if !(1.0..=6.0).contains(&options.sound_speed) {
+        return Err("sound speed must be in 1..=6".to_owned());
+    }
+    if !(1.0..=8.0).contains(&options.over_sample) {
+        return Err("over sample must be in 1..=8".to_owned());
+    }
+    if options.seed >= 1 << 16 {
+        return Err("seed must be less than 65536".to_owned());
+    }
+    if options.sinc.is_some_and(|z| !(1..=256).contains(&z)) {
+        return Err("sinc zero crossings must be in 1..=256".to_owned());
+    }
+    if options.sample_rate < 8000 {
+        return Err("sample rate must be at least 8000".to_owned());
+    }
+    Ok(Some(options))
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", name, value))
+}
+
+fn write_wav(
+    writer: &mut impl Write,
+    buffer: &[f32],
+    sample_rate: u32,
+    bits: u16,
+) -> std::io::Result<()> {
+    let channels = 1u16;
+    let format = if bits == 32 { 3u16 } else { 1 }; // IEEE float or PCM
+    let block_align = channels * bits / 8;
+    let data_size = buffer.len() as u32 * block_align as u32;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&format.to_le_bytes())?;
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&bits.to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+
+    for &x in buffer {
+        let x = x.clamp(-1.0, 1.0);
+        match bits {
+            16 => writer.write_all(&((x * i16::MAX as f32) as i16).to_le_bytes())?,
+            24 => writer.write_all(&((x * 8388607.0) as i32).to_le_bytes()[..3])?,
+            _ => writer.write_all(&x.to_le_bytes())?,
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse_args() {
+    let parse = |args: &[&str]| parse_args(args.iter().map(|s| s.to_string()));
+    assert!(matches!(parse(&["score.txt", "--help"]), Ok(None)));
+    assert!(matches!(parse(&["-h"]), Ok(None)));
+    let options = parse(&["score.txt", "-b", "24"]).unwrap().unwrap();
+    assert_eq!((options.input.as_str(), options.bits), ("score.txt", 24));
+    assert!(parse(&[]).is_err());
+    assert!(parse(&["score.txt", "-b", "8"]).is_err());
+}
+
+#[test]
+fn test_write_wav() {
+    let u16_at = |wav: &[u8], i: usize| u16::from_le_bytes([wav[i], wav[i + 1]]);
+    let u32_at = |wav: &[u8], i: usize| u32::from_le_bytes(wav[i..i + 4].try_into().unwrap());
+    for (bits, format) in [(16, 1), (24, 1), (32, 3)] {
+        let mut wav = Vec::new();
+        write_wav(&mut wav, &[0.0, 1.0, -2.0], 44100, bits).unwrap();
+        let block_align = bits as u32 / 8;
+        assert_eq!(wav.len() as u32, 44 + 3 * block_align);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32_at(&wav, 4), wav.len() as u32 - 8);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&wav, 16), 16);
+        assert_eq!(u16_at(&wav, 20), format);
+        assert_eq!(u16_at(&wav, 22), 1);
+        assert_eq!(u32_at(&wav, 24), 44100);
+        assert_eq!(u32_at(&wav, 28), 44100 * block_align);
+        assert_eq!(u16_at(&wav, 32) as u32, block_align);
+        assert_eq!(u16_at(&wav, 34), bits);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32_at(&wav, 40), 3 * block_align);
+    }
+
+    // Samples are clipped
+    let mut wav = Vec::new();
+    write_wav(&mut wav, &[0.0, 1.0, -2.0], 44100, 16).unwrap();
+    let samples: Vec<i16> = wav[44..]
+        .chunks(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    assert_eq!(samples, vec![0, i16::MAX, -i16::MAX]);
+}
@@ -0,0 +1,204 @@
+//! Searches the tract shape whose formants match the given frequencies.
+
+use crate::{
+    formant::{find_formants, Formant},
+    Benihora,
+};
+
+/// Shorter than the response of `tract_formants` to keep the search fast
+const RESPONSE_LENGTH: usize = 2048;
+/// Constrictions are kept open so that the formants don't vanish
+const CONSTRICTION_DIAMETER_RANGE: (f32, f32) = (0.5, 3.0);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FittedShape {
+    pub tongue: (f32, f32),
+    pub other_constrictions: Vec<(f32, f32)>,
+    pub formants: Vec<Formant>,
+    /// Root mean square of the formant errors in semitones
+    pub error: f32,
+}
+
+/// Finds the tongue and `other_constrictions` that bring the formants of `benihora`
+/// closest to `targets` (F1, F2, ... in Hz).
+/// The constrictions of `benihora` are the starting points, so add one at the lips
+/// beforehand to fit a rounded vowel.
+/// The search time grows threefold with each constriction.
+/// The tract length, the velum and the losses of `benihora` are kept as they are.
+/// Without `targets`, the current shape of `benihora` is returned as it is.
+pub fn fit_shape(benihora: &Benihora, targets: &[f32]) -> FittedShape {
+    let mut fitter = Fitter {
+        benihora: benihora.clone(),
+        targets,
+        max_frequency: targets.iter().fold(0.0f32, |a, &b| a.max(b)) * 1.3 + 500.0,
+    };
+    let source = &benihora.tract.source;
+    if targets.is_empty() {
+        return FittedShape {
+            tongue: source.tongue,
+            other_constrictions: source.other_constrictions.clone(),
+            formants: Vec::new(),
+            error: 0.0,
+        };
+    }
+
+    // Coarse grid search over the tongue and the constriction diameters
+    let index_range = (
+        source.blade_start as f32 + 2.0,
+        source.tip_start as f32 - 3.0,
+    );
+    let diameter_range = (2.05, 3.5);
+    let constriction_count = source.other_constrictions.len();
+    let mut best = (f32::INFINITY, Vec::new());
+    for c in 0..3usize.pow(constriction_count as u32) {
+        for i in 0..=8 {
+            for j in 0..=6 {
+                let tongue = source.tongue_clamp(
+                    crate::lerp(index_range.0, index_range.1, i as f32 / 8.0),
+                    crate::lerp(diameter_range.0, diameter_range.1, j as f32 / 6.0),
+                );
+                let mut parameters = vec![tongue.0, tongue.1];
+                for (k, constriction) in source.other_constrictions.iter().enumerate() {
+                    let diameter = match c / 3usize.pow(k as u32) % 3 {
+                        0 => constriction.1,
+                        1 => 2.0,
+                        _ => 1.0,
+                    };
+                    parameters.extend([constriction.0, diameter]);
+                }
+                let parameters = fitter.clamp(parameters);
+                let error = fitter.error(&parameters);
+                if error < best.0 {
+                    best = (error, parameters);
+                }
+            }
+        }
+    }
+
+    // Refine the tongue and the constrictions by coordinate descent
+    let (mut error, mut parameters) = best;
+    let mut steps: Vec<f32> = parameters.iter().map(|_| 1.0).collect();
+    for _ in 0..100 {
+        let mut improved = false;
+        for k in 0..parameters.len() {
+            for direction in [1.0, -1.0] {
+                let mut candidate = parameters.clone();
+                candidate[k] += direction * steps[k];
+                let candidate = fitter.clamp(candidate);
+                let candidate_error = fitter.error(&candidate);
+                if candidate_error < error {
+                    (error, parameters) = (candidate_error, candidate);
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        if !improved {
+            for step in &mut steps {
+                *step *= 0.5;
+            }
+            if steps[0] < 0.02 {
+                break;
+            }
+        }
+    }
+
+    let formants = fitter.formants(&parameters);
+    let (tongue, other_constrictions) = unpack(&parameters);
+    FittedShape {
+        tongue,
+        other_constrictions,
+        formants,
+        error,
+    }
+}
+
+struct Fitter<'a> {
+    benihora: Benihora,
+    targets: &'a [f32],
+    max_frequency: f32,
+}
+
+impl<'a> Fitter<'a> {
+    fn clamp(&self, mut parameters: Vec<f32>) -> Vec<f32> {
+        let source = &self.benihora.tract.source;
+        (parameters[0], parameters[1]) = source.tongue_clamp(parameters[0], parameters[1]);
+        for constriction in parameters[2..].chunks_mut(2) {
+            constriction[0] = constriction[0].clamp(2.0, source.length as f32 - 1.0);
+            constriction[1] =
+                constriction[1].clamp(CONSTRICTION_DIAMETER_RANGE.0, CONSTRICTION_DIAMETER_RANGE.1);
+        }
+        parameters
+    }
+
+    fn formants(&mut self, parameters: &[f32]) -> Vec<Formant> {
+        let tract = &mut self.benihora.tract;
+        (tract.source.tongue, tract.source.other_constrictions) = unpack(parameters);
+        tract.update_diameter();
+        tract.current_diameter = tract.target_diameter.clone();
+        tract.update_block(0.0);
+        let (response, sample_rate) =
+            crate::tract_impulse_response(RESPONSE_LENGTH, &self.benihora);
+        find_formants(&response, sample_rate, self.max_frequency)
+    }
+
+    fn error(&mut self, parameters: &[f32]) -> f32 {
+        let formants = self.formants(parameters);
+        let sum: f32 = self
+            .targets
+            .iter()
+            .enumerate()
+            .map(|(i, target)| match formants.get(i) {
+                Some(formant) => (12.0 * (formant.frequency / target).log2()).powi(2),
+                // A missing formant counts as an octave off
+                None => 144.0,
+            })
+            .sum();
+        (sum / self.targets.len() as f32).sqrt()
+    }
+}
+
+fn unpack(parameters: &[f32]) -> ((f32, f32), Vec<(f32, f32)>) {
+    (
+        (parameters[0], parameters[1]),
+        parameters[2..].chunks(2).map(|c| (c[0], c[1])).collect(),
+    )
+}
+
+#[test]
+fn test_fit_shape() {
+    let mut benihora = Benihora::new(
+        3.0,
+        48000.0,
+        1.0,
+        0,
+        false,
+        &crate::tract::TractGeometry::default(),
+    );
+    benihora.tract.source.other_constrictions = vec![(41.0, 3.0)];
+
+    // The formants of a known shape are recovered
+    let tongue = benihora.tract.source.tongue_clamp(16.0, 2.6);
+    let mut fitter = Fitter {
+        benihora: benihora.clone(),
+        targets: &[],
+        max_frequency: 6000.0,
+    };
+    let targets: Vec<f32> = fitter
+        .formants(&[tongue.0, tongue.1, 41.0, 1.5])
+        .iter()
+        .take(3)
+        .map(|f| f.frequency)
+        .collect();
+    assert_eq!(targets.len(), 3);
+
+    let fitted = fit_shape(&benihora, &targets);
+    assert!(fitted.error < 0.5, "{:?} {:?}", targets, fitted);
+    assert_eq!(fitted.other_constrictions.len(), 1);
+
+    // Nothing to fit
+    let fitted = fit_shape(&benihora, &[]);
+    assert_eq!(fitted.tongue, benihora.tract.source.tongue);
+    assert_eq!(fitted.other_constrictions, vec![(41.0, 3.0)]);
+    assert_eq!(fitted.error, 0.0);
+}
@@ -0,0 +1,300 @@
+//! Formant frequencies and bandwidths by peak-picking the spectrum of an impulse response,
+//! or of the linear prediction envelope of a recording.
+
+use std::f32::consts::PI;
+
+use crate::Benihora;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Formant {
+    /// Hz
+    pub frequency: f32,
+    /// Half-power bandwidth in Hz
+    pub bandwidth: f32,
+}
+
+/// Length of the impulse response to analyze.
+/// 8192 steps are enough for the resonances to decay at the usual step rates.
+const RESPONSE_LENGTH: usize = 8192;
+/// The linear prediction envelope decays faster than the tract
+const LPC_RESPONSE_LENGTH: usize = 2048;
+/// Frequency resolution of the peak-picking in Hz
+const STEP: f32 = 5.0;
+/// Peaks below this are the DC component rather than formants
+const MIN_FREQUENCY: f32 = 90.0;
+
+/// Returns F1-F5 of the current tract shape.
+/// Fewer formants are returned if some resonances are merged or above 6 kHz.
+pub fn tract_formants(benihora: &Benihora) -> Vec<Formant> {
+    let (response, sample_rate) = crate::tract_impulse_response(RESPONSE_LENGTH, benihora);
+    let mut formants = find_formants(&response, sample_rate, 6000.0);
+    formants.truncate(5);
+    formants
+}
+
+/// Returns the resonances of `response` below `max_frequency` in ascending order.
+pub fn find_formants(response: &[f32], sample_rate: f32, max_frequency: f32) -> Vec<Formant> {
+    let frequencies: Vec<f32> = (0..)
+        .map(|i| MIN_FREQUENCY - STEP + i as f32 * STEP)
+        .take_while(|f| *f <= max_frequency.min(sample_rate / 2.0) + STEP)
+        .collect();
+    let power: Vec<f32> = frequencies
+        .iter()
+        .map(|f| power_at(response, 2.0 * PI * f / sample_rate))
+        .collect();
+    pick_formants(&frequencies, &power, STEP, max_frequency)
+}
+
+/// Same as `find_formants` but from the power spectrum of the response, where `power[i]` is
+/// at `i * step` Hz, e.g. an FFT of the zero-padded response. A finer step is more precise.
+pub fn find_formants_in_spectrum(power: &[f32], step: f32, max_frequency: f32) -> Vec<Formant> {
+    let frequencies: Vec<f32> = (0..power.len()).map(|i| i as f32 * step).collect();
+    pick_formants(&frequencies, power, step, max_frequency)
+}
+
+/// `power` is sampled at `frequencies`, which are evenly spaced by `step`.
+fn pick_formants(
+    frequencies: &[f32],
+    power: &[f32],
+    step: f32,
+    max_frequency: f32,
+) -> Vec<Formant> {
+    // A peak needs a bin on each side
+    if power.len() < 3 {
+        return Vec::new();
+    }
+    let level: Vec<f32> = power
+        .iter()
+        .map(|p| p.max(f32::MIN_POSITIVE).ln())
+        .collect();
+
+    let mut formants = Vec::new();
+    for i in 1..power.len() - 1 {
+        if !(power[i - 1] < power[i] && power[i + 1] <= power[i]) {
+            continue;
+        }
+
+        // Parabolic interpolation of the log power
+        let curvature = level[i - 1] - 2.0 * level[i] + level[i + 1];
+        let offset = if curvature < 0.0 {
+            (0.5 * (level[i - 1] - level[i + 1]) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        let frequency = frequencies[i] + offset * step;
+
+        // Walk down to the half-power points, stopping at the valleys
+        let half = power[i] / 2.0;
+        let crossing = |j: usize, k: usize| {
+            // Interpolated position of the crossing between the neighbors j and k
+            let t = (power[j] - half) / (power[j] - power[k]);
+            (frequencies[j] + (frequencies[k] - frequencies[j]) * t - frequency).abs()
+        };
+        let mut lower = None;
+        for j in (0..i).rev() {
+            if power[j] < half {
+                lower = Some(crossing(j + 1, j));
+                break;
+            }
+            if power[j + 1] < power[j] {
+                break;
+            }
+        }
+        let mut upper = None;
+        for j in i + 1..power.len() {
+            if power[j] < half {
+                upper = Some(crossing(j - 1, j));
+                break;
+            }
+            if power[j - 1] < power[j] {
+                break;
+            }
+        }
+        let bandwidth = match (lower, upper) {
+            (Some(lower), Some(upper)) => lower + upper,
+            (Some(width), None) | (None, Some(width)) => 2.0 * width,
+            // The log power of a resonance has the curvature -8 / bandwidth^2 at the peak
+            (None, None) if curvature < 0.0 => (-8.0 / curvature).sqrt() * step,
+            (None, None) => continue,
+        };
+
+        if MIN_FREQUENCY <= frequency && frequency <= max_frequency {
+            formants.push(Formant {
+                frequency,
+                bandwidth,
+            });
+        }
+    }
+    formants
+}
+
+/// Estimates the formants of a voiced `signal` by linear prediction.
+/// `order` is the number of poles, about `2 + sample_rate / 1000` for speech.
+pub fn lpc_formants(signal: &[f32], sample_rate: f32, order: usize) -> Vec<Formant> {
+    // Pre-emphasis and Hann window
+    let n = signal.len();
+    let windowed: Vec<f64> = (0..n)
+        .map(|i| {
+            let x = signal[i] - 0.97 * if i == 0 { 0.0 } else { signal[i - 1] };
+            x as f64 * 0.5 * (1.0 - (2.0 * std::f64::consts::PI * i as f64 / n as f64).cos())
+        })
+        .collect();
+    // The linear prediction is ill-conditioned for narrow resonances, so it is done in f64
+    let mut autocorrelation: Vec<f64> = (0..=order)
+        .map(|lag| {
+            windowed[lag.min(n)..]
+                .iter()
+                .zip(&windowed)
+                .map(|(a, b)| a * b)
+                .sum()
+        })
+        .collect();
+    // White noise correction
+    autocorrelation[0] *= 1.0001;
+
+    // Levinson-Durbin recursion
+    let mut a = vec![0.0; order + 1];
+    a[0] = 1.0;
+    let mut error = autocorrelation[0];
+    if error <= 0.0 {
+        return Vec::new();
+    }
+    for i in 1..=order {
+        let acc: f64 = (0..i).map(|j| a[j] * autocorrelation[i - j]).sum();
+        let k = -acc / error;
+        let previous = a.clone();
+        for j in 1..i {
+            a[j] = previous[j] + k * previous[i - j];
+        }
+        a[i] = k;
+        error *= 1.0 - k * k;
+    }
+
+    // The peaks of the all-pole envelope are the formants
+    let mut history = vec![0.0; order];
+    let response = crate::impulse_response(LPC_RESPONSE_LENGTH, |x| {
+        let y = x as f64 - a[1..].iter().zip(&history).map(|(a, y)| a * y).sum::<f64>();
+        history.rotate_right(1);
+        history[0] = y;
+        y as f32
+    });
+    find_formants(&response, sample_rate, 5000.0)
+}
+
+/// Power of the DFT at the angular frequency `w` by the Goertzel algorithm
+fn power_at(signal: &[f32], w: f32) -> f32 {
+    let coefficient = 2.0 * w.cos();
+    let (mut s1, mut s2) = (0.0, 0.0);
+    for x in signal {
+        let s = x + coefficient * s1 - s2;
+        s2 = s1;
+        s1 = s;
+    }
+    s1 * s1 + s2 * s2 - coefficient * s1 * s2
+}
+
+#[test]
+fn test_find_formants() {
+    // Cascade of two-pole resonators
+    let sample_rate = 48000.0;
+    let targets = [(500.0, 60.0), (1500.0, 90.0), (2500.0, 120.0)];
+    let mut response = crate::impulse_response(8192, |x| x);
+    for (frequency, bandwidth) in targets {
+        let r = (-PI * bandwidth / sample_rate).exp();
+        let a1 = 2.0 * r * (2.0 * PI * frequency / sample_rate).cos();
+        let a2 = -r * r;
+        let (mut y1, mut y2) = (0.0, 0.0);
+        for x in response.iter_mut() {
+            let y = *x + a1 * y1 + a2 * y2;
+            y2 = y1;
+            y1 = y;
+            *x = y;
+        }
+    }
+
+    let formants = find_formants(&response, sample_rate, 4000.0);
+    assert_eq!(formants.len(), 3, "{:?}", formants);
+    // The same spectrum from 0 Hz gives the same formants
+    let power: Vec<f32> = (0..=4000 / STEP as usize + 1)
+        .map(|i| power_at(&response, 2.0 * PI * i as f32 * STEP / sample_rate))
+        .collect();
+    assert_eq!(find_formants_in_spectrum(&power, STEP, 4000.0), formants);
+    assert!(find_formants_in_spectrum(&[], STEP, 4000.0).is_empty());
+    assert!(find_formants_in_spectrum(&[1.0, 2.0], STEP, 4000.0).is_empty());
+    for (formant, (frequency, bandwidth)) in formants.iter().zip(targets) {
+        assert!(
+            (formant.frequency - frequency).abs() < 10.0,
+            "{:?}",
+            formant
+        );
+        assert!(
+            (formant.bandwidth - bandwidth).abs() < 15.0,
+            "{:?}",
+            formant
+        );
+    }
+}
+
+#[test]
+fn test_lpc_formants() {
+    // Impulse train at 120 Hz through the resonators
+    let sample_rate = 16000.0;
+    let targets = [(700.0, 80.0), (1200.0, 90.0), (2600.0, 120.0)];
+    let mut signal: Vec<f32> = (0..4000)
+        .map(|i| if i % 133 == 0 { 1.0 } else { 0.0 })
+        .collect();
+    for (frequency, bandwidth) in targets {
+        let r = (-PI * bandwidth / sample_rate).exp();
+        let a1 = 2.0 * r * (2.0 * PI * frequency / sample_rate).cos();
+        let a2 = -r * r;
+        let (mut y1, mut y2) = (0.0, 0.0);
+        for x in signal.iter_mut() {
+            let y = *x + a1 * y1 + a2 * y2;
+            y2 = y1;
+            y1 = y;
+            *x = y;
+        }
+    }
+
+    let formants = lpc_formants(&signal[1000..], sample_rate, 12);
+    for (frequency, _) in targets {
+        assert!(
+            formants
+                .iter()
+                .any(|f| (f.frequency - frequency).abs() < frequency * 0.1),
+            "{:?}",
+            formants
+        );
+    }
+}
+
+#[test]
+fn test_tract_formants() {
+    let formants = |tongue: (f32, f32)| {
+        let mut benihora = Benihora::new(
+            3.0,
+            48000.0,
+            1.0,
+            0,
+            false,
+            &crate::tract::TractGeometry::default(),
+        );
+        benihora.tract.source.tongue = tongue;
+        benihora.tract.update_diameter();
+        benihora.tract.current_diameter = benihora.tract.target_diameter.clone();
+        benihora.tract.update_block(0.0);
+        tract_formants(&benihora)
+    };
+
+    let i = formants((27.2, 2.2));
+    let u = formants((22.8, 2.05));
+    let schwa = formants((17.5, 2.9));
+    for formants in [&i, &u, &schwa] {
+        assert!(2 <= formants.len() && formants.len() <= 5, "{:?}", formants);
+        assert!(formants.windows(2).all(|f| f[0].frequency < f[1].frequency));
+        assert!(formants.iter().all(|f| 0.0 < f.bandwidth), "{:?}", formants);
+    }
+    // Close vowels have a lower F1 than the neutral vowel
+    assert!(i[0].frequency < schwa[0].frequency);
+    assert!(u[0].frequency < schwa[0].frequency);
+}
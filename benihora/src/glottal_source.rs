@@ -0,0 +1,518 @@
+//! Models of the voiced glottal flow.
+//!
+//! The flows are normalized so that the flow derivative per period reaches about -1 at
+//! the glottal closure, which keeps the loudness comparable between the models.
+
+use std::f32::consts::PI;
+
+use crate::{glottis::LiljencrantsFant, lerp};
+
+pub trait GlottalSource {
+    /// Advances the source by one sample and returns the change of the normalized glottal flow.
+    /// Dividing it by `frequency / sample_rate` gives the flow derivative per period.
+    fn process(&mut self, frequency: f32, tenseness: f32, sample_rate: f32) -> f32;
+
+    /// Position in the current glottal cycle, 0..1
+    fn phase(&self) -> f32;
+}
+
+/// The glottal source models provided by this crate
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GlottalModel {
+    LiljencrantsFant(LiljencrantsFant),
+    Rosenberg(Rosenberg),
+    Klglott88(Klglott88),
+    TwoMass(TwoMass),
+    Wavetable(Wavetable),
+}
+
+impl Default for GlottalModel {
+    fn default() -> Self {
+        GlottalModel::LiljencrantsFant(LiljencrantsFant::new())
+    }
+}
+
+impl GlottalModel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GlottalModel::LiljencrantsFant(_) => "Liljencrants-Fant",
+            GlottalModel::Rosenberg(_) => "Rosenberg",
+            GlottalModel::Klglott88(_) => "KLGLOTT88",
+            GlottalModel::TwoMass(_) => "Two-mass",
+            GlottalModel::Wavetable(_) => "Wavetable",
+        }
+    }
+}
+
+impl GlottalSource for GlottalModel {
+    fn process(&mut self, frequency: f32, tenseness: f32, sample_rate: f32) -> f32 {
+        match self {
+            GlottalModel::LiljencrantsFant(source) => {
+                source.process(frequency, tenseness, sample_rate)
+            }
+            GlottalModel::Rosenberg(source) => source.process(frequency, tenseness, sample_rate),
+            GlottalModel::Klglott88(source) => source.process(frequency, tenseness, sample_rate),
+            GlottalModel::TwoMass(source) => source.process(frequency, tenseness, sample_rate),
+            GlottalModel::Wavetable(source) => source.process(frequency, tenseness, sample_rate),
+        }
+    }
+
+    fn phase(&self) -> f32 {
+        match self {
+            GlottalModel::LiljencrantsFant(source) => source.phase(),
+            GlottalModel::Rosenberg(source) => source.phase(),
+            GlottalModel::Klglott88(source) => source.phase(),
+            GlottalModel::TwoMass(source) => source.phase(),
+            GlottalModel::Wavetable(source) => source.phase(),
+        }
+    }
+}
+
+impl From<LiljencrantsFant> for GlottalModel {
+    fn from(source: LiljencrantsFant) -> Self {
+        GlottalModel::LiljencrantsFant(source)
+    }
+}
+
+impl From<Rosenberg> for GlottalModel {
+    fn from(source: Rosenberg) -> Self {
+        GlottalModel::Rosenberg(source)
+    }
+}
+
+impl From<Klglott88> for GlottalModel {
+    fn from(source: Klglott88) -> Self {
+        GlottalModel::Klglott88(source)
+    }
+}
+
+impl From<TwoMass> for GlottalModel {
+    fn from(source: TwoMass) -> Self {
+        GlottalModel::TwoMass(source)
+    }
+}
+
+impl From<Wavetable> for GlottalModel {
+    fn from(source: Wavetable) -> Self {
+        GlottalModel::Wavetable(source)
+    }
+}
+
+/// Open quotient of the pulse models, a tense glottis closes earlier
+fn open_quotient(tenseness: f32) -> f32 {
+    lerp(0.9, 0.35, tenseness)
+}
+
+/// Rosenberg's trigonometric pulse (type C)
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rosenberg {
+    phase: f32,
+    last_flow: f32,
+    /// Durations of the opening and closing phases in periods
+    opening: f32,
+    closing: f32,
+}
+
+impl Rosenberg {
+    pub fn new() -> Self {
+        let mut source = Self {
+            phase: 0.0,
+            last_flow: 0.0,
+            opening: 0.0,
+            closing: 0.0,
+        };
+        source.set_tenseness(0.6);
+        source
+    }
+
+    fn set_tenseness(&mut self, tenseness: f32) {
+        let open_quotient = open_quotient(tenseness);
+        self.opening = open_quotient * 0.7;
+        self.closing = open_quotient * 0.3;
+    }
+
+    fn flow(&self, t: f32) -> f32 {
+        // The derivative is -PI / (2 * closing) at the closure
+        let scale = 2.0 * self.closing / PI;
+        if t < self.opening {
+            scale * 0.5 * (1.0 - (PI * t / self.opening).cos())
+        } else if t < self.opening + self.closing {
+            scale * (0.5 * PI * (t - self.opening) / self.closing).cos()
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Default for Rosenberg {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlottalSource for Rosenberg {
+    fn process(&mut self, frequency: f32, tenseness: f32, sample_rate: f32) -> f32 {
+        self.phase += frequency / sample_rate;
+        if 1.0 < self.phase {
+            self.phase -= 1.0;
+            self.set_tenseness(tenseness);
+            self.last_flow = self.flow(0.0);
+        }
+
+        let flow = self.flow(self.phase);
+        let delta = flow - self.last_flow;
+        self.last_flow = flow;
+        delta
+    }
+
+    fn phase(&self) -> f32 {
+        self.phase
+    }
+}
+
+/// Klatt and Klatt's polynomial pulse with the spectral tilt lowpass
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Klglott88 {
+    phase: f32,
+    last_flow: f32,
+    open_quotient: f32,
+    tilt: f32,
+    tilt_lowpass: f32,
+}
+
+impl Klglott88 {
+    pub fn new() -> Self {
+        Self {
+            phase: 0.0,
+            last_flow: 0.0,
+            open_quotient: open_quotient(0.6),
+            tilt: 0.0,
+            tilt_lowpass: 0.0,
+        }
+    }
+
+    fn flow(&self, t: f32) -> f32 {
+        // a * t^2 - b * t^3, normalized so that the derivative is -1 at the closure
+        if t < self.open_quotient {
+            (t * t - t * t * t / self.open_quotient) / self.open_quotient
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Default for Klglott88 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlottalSource for Klglott88 {
+    fn process(&mut self, frequency: f32, tenseness: f32, sample_rate: f32) -> f32 {
+        self.phase += frequency / sample_rate;
+        if 1.0 < self.phase {
+            self.phase -= 1.0;
+            self.open_quotient = open_quotient(tenseness);
+            // A lax voice has a steeper spectral tilt
+            let cutoff = lerp(1500.0, 8000.0, tenseness);
+            self.tilt = (-2.0 * PI * cutoff / sample_rate).exp();
+            self.last_flow = self.flow(0.0);
+        }
+
+        let flow = self.flow(self.phase);
+        let delta = flow - self.last_flow;
+        self.last_flow = flow;
+        self.tilt_lowpass = lerp(delta, self.tilt_lowpass, self.tilt);
+        self.tilt_lowpass
+    }
+
+    fn phase(&self) -> f32 {
+        self.phase
+    }
+}
+
+/// Self-oscillating two-mass model of the vocal folds (Ishizaka and Flanagan 1972,
+/// simplified by Steinecke and Herzel 1995).
+/// The upper and lower parts of each fold are coupled masses driven by the subglottal
+/// pressure, so the pulses have small natural irregularities.
+/// The frequency is set by scaling the tension and the tenseness by the adduction.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TwoMass {
+    /// Displacements in cm and velocities in cm/s of the lower and upper masses
+    /// of the left and right folds
+    x: [[f32; 2]; 2],
+    v: [[f32; 2]; 2],
+    /// Tension imbalance of the right fold against the left one, 1 for symmetric folds.
+    /// The right fold is stiffer and lighter by this factor like a unilateral paralysis,
+    /// which makes the folds vibrate out of phase and the voice rough.
+    pub asymmetry: f32,
+    phase: f32,
+    last_flow: f32,
+    mean_flow: f32,
+    open: bool,
+}
+
+impl TwoMass {
+    // Parameters in CGS units
+    const MASS: [f32; 2] = [0.125, 0.025];
+    const STIFFNESS: [f32; 2] = [80000.0, 8000.0];
+    const COUPLING: f32 = 25000.0;
+    const DAMPING_RATIO: [f32; 2] = [0.1, 0.6];
+    /// Additional damping ratio while the folds are in contact
+    const CONTACT_DAMPING_RATIO: f32 = 1.0;
+    /// Thickness of the masses
+    const THICKNESS: [f32; 2] = [0.25, 0.05];
+    /// Length of the folds
+    const LENGTH: f32 = 1.4;
+    const SUBGLOTTAL_PRESSURE: f32 = 8000.0;
+    const AIR_DENSITY: f32 = 0.00113;
+    /// Oscillation frequency with the unscaled tension
+    const NATURAL_FREQUENCY: f32 = 132.0;
+    /// Flow that is normalized to 1, chosen to roughly match the loudness of the pulse models
+    const FLOW_SCALE: f32 = 3500.0;
+    const SUBSTEPS: usize = 4;
+
+    pub fn new() -> Self {
+        Self {
+            // Displaced a bit to start the oscillation
+            x: [[0.02, 0.0]; 2],
+            v: [[0.0; 2]; 2],
+            asymmetry: 1.0,
+            phase: 0.0,
+            last_flow: 0.0,
+            mean_flow: 0.0,
+            open: false,
+        }
+    }
+
+    /// Areas between the folds at the lower and upper masses
+    fn area(&self, rest_area: f32) -> [f32; 2] {
+        [0, 1].map(|i| rest_area + Self::LENGTH * (self.x[0][i] + self.x[1][i]))
+    }
+
+    fn step(&mut self, q: f32, rest_area: f32, dtime: f32) {
+        let area = self.area(rest_area);
+        let min_area = area[0].min(area[1]);
+        let pressure = if area[0] <= 0.0 {
+            0.0
+        } else if min_area <= 0.0 {
+            Self::SUBGLOTTAL_PRESSURE
+        } else {
+            Self::SUBGLOTTAL_PRESSURE * (1.0 - (min_area / area[0]).powi(2))
+        };
+
+        for (side, tension) in [(0, q), (1, q * self.asymmetry)] {
+            let (x, v) = (&mut self.x[side], &mut self.v[side]);
+            let mut force = [Self::LENGTH * Self::THICKNESS[0] * pressure, 0.0];
+            for i in 0..2 {
+                let mass = Self::MASS[i] / tension;
+                let stiffness = Self::STIFFNESS[i] * tension;
+                let mut damping_ratio = Self::DAMPING_RATIO[i];
+                if area[i] < 0.0 {
+                    // The folds collide and push each other back
+                    force[i] -= 3.0 * stiffness * area[i] / (2.0 * Self::LENGTH);
+                    damping_ratio += Self::CONTACT_DAMPING_RATIO;
+                }
+                force[i] -= stiffness * x[i]
+                    + Self::COUPLING * tension * (x[i] - x[1 - i])
+                    + 2.0 * damping_ratio * (mass * stiffness).sqrt() * v[i];
+                v[i] += force[i] / mass * dtime;
+            }
+            for i in 0..2 {
+                x[i] += v[i] * dtime;
+            }
+        }
+    }
+
+    fn flow(&self, rest_area: f32) -> f32 {
+        let area = self.area(rest_area);
+        let min_area = area[0].min(area[1]);
+        (2.0 * Self::SUBGLOTTAL_PRESSURE / Self::AIR_DENSITY).sqrt() * min_area.max(0.0)
+            / Self::FLOW_SCALE
+    }
+}
+
+impl Default for TwoMass {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlottalSource for TwoMass {
+    fn process(&mut self, frequency: f32, tenseness: f32, sample_rate: f32) -> f32 {
+        let q = frequency / Self::NATURAL_FREQUENCY;
+        // A lax glottis is abducted and doesn't close completely
+        let rest_area = lerp(0.08, 0.0, tenseness);
+        let dtime = 1.0 / (sample_rate * Self::SUBSTEPS as f32);
+        for _ in 0..Self::SUBSTEPS {
+            self.step(q, rest_area, dtime);
+        }
+
+        // The amplitude of the folds is inversely proportional to the tension
+        let flow = self.flow(rest_area) * q;
+
+        self.phase = (self.phase + frequency / sample_rate).fract();
+        // The cycle starts when the flow rises above its mean
+        self.mean_flow = lerp(self.mean_flow, flow, frequency / sample_rate);
+        if !self.open && self.mean_flow * 1.2 < flow {
+            self.open = true;
+            self.phase = 0.0;
+        } else if self.open && flow < self.mean_flow * 0.8 {
+            self.open = false;
+        }
+
+        let delta = flow - self.last_flow;
+        self.last_flow = flow;
+        delta
+    }
+
+    fn phase(&self) -> f32 {
+        self.phase
+    }
+}
+
+/// User-supplied flow derivative waveforms.
+/// The tenseness morphs between the waveforms.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Wavetable {
+    /// Integrals of the waveforms, each has one more sample than the waveform
+    flows: Vec<Vec<f32>>,
+    phase: f32,
+    last_flow: f32,
+    position: f32,
+}
+
+impl Wavetable {
+    /// `waveforms`: One period of the flow derivative each, ordered from lax to tense.
+    /// The DC offset is removed and the negative peak is normalized to -1.
+    pub fn new(waveforms: &[Vec<f32>]) -> Self {
+        assert!(!waveforms.is_empty());
+        let flows = waveforms
+            .iter()
+            .map(|waveform| {
+                assert!(2 <= waveform.len());
+                let mean = waveform.iter().sum::<f32>() / waveform.len() as f32;
+                let peak = waveform
+                    .iter()
+                    .map(|x| mean - x)
+                    .fold(f32::EPSILON, f32::max);
+                let mut flow = Vec::with_capacity(waveform.len() + 1);
+                flow.push(0.0);
+                for x in waveform {
+                    flow.push(flow.last().unwrap() + (x - mean) / peak / waveform.len() as f32);
+                }
+                flow
+            })
+            .collect();
+        Self {
+            flows,
+            phase: 0.0,
+            last_flow: 0.0,
+            position: 0.0,
+        }
+    }
+
+    fn flow(&self, t: f32) -> f32 {
+        let sample = |flow: &Vec<f32>| {
+            let x = t * (flow.len() - 1) as f32;
+            let i = (x.floor() as usize).min(flow.len() - 2);
+            lerp(flow[i], flow[i + 1], x - i as f32)
+        };
+        let i = (self.position.floor() as usize).min(self.flows.len() - 1);
+        let j = (i + 1).min(self.flows.len() - 1);
+        lerp(
+            sample(&self.flows[i]),
+            sample(&self.flows[j]),
+            self.position - i as f32,
+        )
+    }
+}
+
+impl GlottalSource for Wavetable {
+    fn process(&mut self, frequency: f32, tenseness: f32, sample_rate: f32) -> f32 {
+        self.phase += frequency / sample_rate;
+        if 1.0 < self.phase {
+            self.phase -= 1.0;
+            self.position = tenseness * (self.flows.len() - 1) as f32;
+            self.last_flow = self.flow(0.0);
+        }
+
+        let flow = self.flow(self.phase);
+        let delta = flow - self.last_flow;
+        self.last_flow = flow;
+        delta
+    }
+
+    fn phase(&self) -> f32 {
+        self.phase
+    }
+}
+
+#[test]
+fn test_glottal_sources() {
+    let sample_rate = 48000.0;
+    let frequency = 150.0;
+    let sources: Vec<GlottalModel> = vec![
+        LiljencrantsFant::new().into(),
+        Rosenberg::new().into(),
+        Klglott88::new().into(),
+        TwoMass::new().into(),
+        Wavetable::new(&[vec![0.0, 1.0, 0.5, -1.0, 0.0, 0.0]]).into(),
+    ];
+    for mut source in sources {
+        let mut cycles = 0;
+        let mut last_phase = 0.0;
+        let mut min = 0.0f32;
+        for i in 0..48000 {
+            let derivative =
+                source.process(frequency, 0.6, sample_rate) / (frequency / sample_rate);
+            assert!(derivative.is_finite());
+            if 24000 <= i {
+                min = min.min(derivative);
+                if source.phase() < last_phase {
+                    cycles += 1;
+                }
+            }
+            last_phase = source.phase();
+        }
+        assert!((-1.3..-0.5).contains(&min), "{}: {}", source.name(), min);
+        assert!((73..=77).contains(&cycles), "{}: {}", source.name(), cycles);
+    }
+}
+
+#[test]
+fn test_two_mass_asymmetry() {
+    let sample_rate = 48000.0;
+    let mut symmetric = TwoMass::new();
+    let mut asymmetric = TwoMass {
+        asymmetry: 1.3,
+        ..TwoMass::new()
+    };
+    let mut difference = 0.0f32;
+    for _ in 0..48000 {
+        let a = symmetric.process(150.0, 0.6, sample_rate);
+        let b = asymmetric.process(150.0, 0.6, sample_rate);
+        assert!(b.is_finite());
+        difference = difference.max((a - b).abs());
+    }
+    // The folds move together only if they are symmetric
+    assert_eq!(symmetric.x[0], symmetric.x[1]);
+    assert_ne!(asymmetric.x[0], asymmetric.x[1]);
+    assert!(0.0 < difference);
+
+    let benihora = crate::Benihora::with_glottal_source(
+        3.0,
+        sample_rate,
+        1.0,
+        0,
+        false,
+        &crate::tract::TractGeometry::default(),
+        asymmetric,
+    );
+    assert!(matches!(benihora.glottis.source, GlottalModel::TwoMass(_)));
+}
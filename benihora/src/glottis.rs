@@ -1,31 +1,70 @@
 use std::f32::consts::PI;
 
-use crate::{lerp, noise::Noise, wiggle::Wiggle};
+use crate::{
+    glottal_source::{GlottalModel, GlottalSource},
+    lerp,
+    noise::Noise,
+    rand_f32,
+    wiggle::Wiggle,
+};
 
-pub struct Glottis {
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Glottis<S = GlottalModel> {
     pub(crate) aspiration_noise: Noise,
-    phase: f32,
-    waveform: WaveformIntegral,
+    pub source: S,
     sample_rate: f32,
     wiggle: Wiggle,
-    last_integral: f32,
+    pub voice_quality: VoiceQuality,
+    rand: u32,
+    odd_cycle: bool,
+    period_scale: f32,
+    amplitude: f32,
+    leak_lowpass: f32,
+}
+
+/// Irregularities of the glottal pulses that the tenseness doesn't cover.
+/// All zero is the regular voice.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VoiceQuality {
+    /// Random deviation of each period relative to the period, e.g. 0.01 for 1%
+    pub jitter: f32,
+    /// Random deviation of each pulse amplitude relative to the amplitude
+    pub shimmer: f32,
+    /// 0..1, alternates long strong pulses and short weak pulses (diplophonia).
+    /// At 1 every other pulse almost vanishes and a subharmonic an octave below appears
+    /// like vocal fry.
+    pub creak: f32,
+    /// 0..1, air leaking through a glottis that doesn't close completely.
+    /// Softens the closure and adds a steady breath noise even to a tense voice.
+    pub breath_leak: f32,
 }
 
 impl Glottis {
     pub fn new(sample_rate: f32, seed: u32) -> Self {
-        let waveform = WaveformIntegral::new(&Waveform::new(0.6));
+        Self::with_source(sample_rate, seed, GlottalModel::default())
+    }
+}
+
+impl<S: GlottalSource> Glottis<S> {
+    pub fn with_source(sample_rate: f32, seed: u32, source: S) -> Self {
         Self {
             aspiration_noise: Noise::new(seed + 1, sample_rate, 500.0),
-            phase: 0.0,
-            last_integral: waveform.compute(0.0),
-            waveform,
+            source,
             sample_rate,
             wiggle: Wiggle::new(1.0 / sample_rate, 10.0, seed + 2),
+            voice_quality: VoiceQuality::default(),
+            rand: seed + 3,
+            odd_cycle: false,
+            period_scale: 1.0,
+            amplitude: 1.0,
+            leak_lowpass: 0.0,
         }
     }
 
     pub fn get_phase(&self) -> f32 {
-        self.phase
+        self.source.phase()
     }
 
     pub fn process(
@@ -36,37 +75,119 @@ impl Glottis {
         loudness: f32,
         aspiration_level: f32,
     ) -> f32 {
-        let noise = self.aspiration_noise.process();
+        let (out, aspiration) =
+            self.process_split(frequency, tenseness, intensity, loudness, aspiration_level);
+        out + aspiration
+    }
 
+    /// Returns the voiced flow derivative and the aspiration noise separately.
+    pub fn process_split(
+        &mut self,
+        frequency: f32,
+        tenseness: f32,
+        intensity: f32,
+        loudness: f32,
+        aspiration_level: f32,
+    ) -> (f32, f32) {
+        let raw_noise = self.aspiration_noise.process();
+
+        let frequency = frequency / self.period_scale;
         let d = frequency / self.sample_rate;
-        self.phase += d;
-        if 1.0 < self.phase {
-            self.phase -= 1.0;
-            self.waveform = WaveformIntegral::new(&Waveform::new(tenseness));
-            self.last_integral = self.waveform.compute(0.0);
+        let last_phase = self.source.phase();
+        let flow = self.source.process(frequency, tenseness, self.sample_rate);
+        let mut out = intensity * loudness * flow / d * self.amplitude;
+        if self.source.phase() < last_phase {
+            self.start_cycle();
         }
 
-        // let out = intensity * loudness * self.waveform.normalized_lf_waveform(self.phase);
-        let integral = self.waveform.compute(self.phase);
-        let out = intensity * loudness * (integral - self.last_integral) / d;
-        self.last_integral = integral;
+        let breath_leak = self.voice_quality.breath_leak;
+        if breath_leak > 0.0 {
+            let cutoff = lerp(8000.0, 800.0, breath_leak);
+            let pole = (-2.0 * PI * cutoff / self.sample_rate).exp();
+            self.leak_lowpass = lerp(out, self.leak_lowpass, pole);
+            out = self.leak_lowpass;
+        }
 
-        let noise = self.get_noise_modulator(tenseness * intensity) * noise;
-        let aspiration = intensity
+        let noise = self.get_noise_modulator(tenseness * intensity) * raw_noise;
+        let mut aspiration = intensity
             * (1.0 - tenseness.sqrt())
             * noise
             * (0.2 + 0.01 * self.wiggle.process())
             * aspiration_level;
+        if breath_leak > 0.0 {
+            // The leak is not modulated by the glottal cycle
+            aspiration += intensity * breath_leak * 0.2 * raw_noise;
+        }
 
-        out + aspiration
+        (out, aspiration)
+    }
+
+    /// Draws the period and the amplitude of the next pulse.
+    fn start_cycle(&mut self) {
+        let quality = self.voice_quality;
+        self.odd_cycle = !self.odd_cycle;
+        let alternate = if self.odd_cycle { 1.0 } else { -1.0 };
+
+        let jitter = quality.jitter * (rand_f32(&mut self.rand) * 2.0 - 1.0);
+        let shimmer = quality.shimmer * (rand_f32(&mut self.rand) * 2.0 - 1.0);
+        self.period_scale = (1.0 + jitter + 0.25 * quality.creak * alternate).max(0.1);
+        self.amplitude =
+            (1.0 + shimmer).max(0.0) * (1.0 - 0.45 * quality.creak * (1.0 - alternate));
     }
 
     fn get_noise_modulator(&mut self, rate: f32) -> f32 {
-        let voiced = 0.1 + 0.2 * 0.0f32.max((PI * 2.0 * self.phase).sin());
+        let voiced = 0.1 + 0.2 * 0.0f32.max((PI * 2.0 * self.get_phase()).sin());
         lerp(0.3, voiced, rate)
     }
 }
 
+/// Liljencrants-Fant model parameterized by the tenseness via Rd
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LiljencrantsFant {
+    phase: f32,
+    waveform: WaveformIntegral,
+    last_integral: f32,
+}
+
+impl LiljencrantsFant {
+    pub fn new() -> Self {
+        let waveform = WaveformIntegral::new(&Waveform::new(0.6));
+        Self {
+            phase: 0.0,
+            last_integral: waveform.compute(0.0),
+            waveform,
+        }
+    }
+}
+
+impl Default for LiljencrantsFant {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlottalSource for LiljencrantsFant {
+    fn process(&mut self, frequency: f32, tenseness: f32, sample_rate: f32) -> f32 {
+        self.phase += frequency / sample_rate;
+        if 1.0 < self.phase {
+            self.phase -= 1.0;
+            self.waveform = WaveformIntegral::new(&Waveform::new(tenseness));
+            self.last_integral = self.waveform.compute(0.0);
+        }
+
+        // let out = self.waveform.normalized_lf_waveform(self.phase);
+        let integral = self.waveform.compute(self.phase);
+        let delta = integral - self.last_integral;
+        self.last_integral = integral;
+        delta
+    }
+
+    fn phase(&self) -> f32 {
+        self.phase
+    }
+}
+
 /// Liljencrants-Fant waveform
 struct Waveform {
     alpha: f32,
@@ -135,6 +256,8 @@ impl Waveform {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WaveformIntegral {
     te: f32,
     e0: f32,
@@ -181,3 +304,57 @@ impl WaveformIntegral {
         }
     }
 }
+
+#[test]
+fn test_voice_quality() {
+    // Returns the period in samples and the negative peak of each pulse
+    fn pulses(voice_quality: VoiceQuality) -> Vec<(usize, f32)> {
+        let mut glottis = Glottis::new(48000.0, 1);
+        glottis.voice_quality = voice_quality;
+        let mut pulses = Vec::new();
+        let mut start = 0;
+        let mut peak = 0.0f32;
+        let mut last_phase = 0.0;
+        for i in 0..48000 {
+            let (out, _) = glottis.process_split(100.0, 0.6, 1.0, 1.0, 0.0);
+            if glottis.get_phase() < last_phase {
+                pulses.push((i - start, peak));
+                start = i;
+                peak = 0.0;
+            }
+            peak = peak.min(out);
+            last_phase = glottis.get_phase();
+        }
+        pulses.drain(..2);
+        pulses
+    }
+
+    let regular = pulses(VoiceQuality::default());
+    assert!(regular
+        .iter()
+        .all(|&(period, _)| (479..=481).contains(&period)));
+
+    let jittery = pulses(VoiceQuality {
+        jitter: 0.05,
+        shimmer: 0.2,
+        ..Default::default()
+    });
+    let periods: Vec<_> = jittery.iter().map(|&(period, _)| period).collect();
+    assert!(periods.iter().any(|&p| p < 470) && periods.iter().any(|&p| 490 < p));
+    assert!(periods.iter().all(|&p| (450..=510).contains(&p)));
+
+    // Every other pulse is long and strong
+    let creaky = pulses(VoiceQuality {
+        creak: 1.0,
+        ..Default::default()
+    });
+    for pair in creaky.windows(2) {
+        let (long, short) = if pair[0].0 > pair[1].0 {
+            (pair[0], pair[1])
+        } else {
+            (pair[1], pair[0])
+        };
+        assert!(long.0 > short.0 + 200);
+        assert!(long.1 < short.1 * 5.0);
+    }
+}
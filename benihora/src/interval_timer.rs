@@ -1,3 +1,5 @@
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IntervalTimer {
     pub interval: f32,
     pub time: f32,
@@ -1,13 +1,20 @@
+pub mod area_function;
+pub mod articulator;
 mod benihora;
+pub mod fit;
+pub mod formant;
+pub mod glottal_source;
 pub mod glottis;
 mod interval_timer;
 pub mod managed;
 mod noise;
+pub mod phoneme;
+pub mod pitch;
 pub mod resample;
 pub mod tract;
 pub mod wiggle;
 
-pub use self::benihora::Benihora;
+pub use self::benihora::{Benihora, Buses};
 pub use glottis::Glottis;
 pub use interval_timer::IntervalTimer;
 pub use managed::BenihoraManaged;
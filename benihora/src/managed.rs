@@ -1,6 +1,6 @@
 use std::f32::consts::TAU;
 
-use crate::{lerp, wiggle::Wiggle, Benihora, IntervalTimer};
+use crate::{lerp, tract::TractGeometry, wiggle::Wiggle, Benihora, IntervalTimer};
 
 pub struct BenihoraManaged {
     pub sound: bool,
@@ -15,6 +15,16 @@ pub struct BenihoraManaged {
 
 impl BenihoraManaged {
     pub fn new(sound_speed: f32, sample_rate: f32, seed: u32) -> Self {
+        Self::with_over_sample(sound_speed, sample_rate, 1.0, seed)
+    }
+
+    /// Same as `new` but the glottis and the tract run at `over_sample` times the rate.
+    pub fn with_over_sample(
+        sound_speed: f32,
+        sample_rate: f32,
+        over_sample: f32,
+        seed: u32,
+    ) -> Self {
         assert!(seed < 1 << 16);
         let interval = 0.02;
         Self {
@@ -23,7 +33,14 @@ impl BenihoraManaged {
             tenseness: Tenseness::new(interval, seed + 2, 0.6),
             intensity: Intensity::new(0.0),
             loudness: Loudness::new(0.6f32.powf(0.25)),
-            benihora: Benihora::new(sound_speed, sample_rate, 1.0, seed, true),
+            benihora: Benihora::new(
+                sound_speed,
+                sample_rate,
+                over_sample,
+                seed,
+                true,
+                &TractGeometry::default(),
+            ),
             update_timer: IntervalTimer::new_overflowed(interval),
             dtime: 1.0 / sample_rate,
         }
@@ -2,8 +2,16 @@ use biquad::{Biquad, Coefficients, DirectForm2Transposed, ToHertz};
 
 use crate::rand_f32;
 
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "NoiseState", into = "NoiseState")
+)]
 pub struct Noise {
     rand: u32,
+    sample_rate: f32,
+    frequency: f32,
     filter: DirectForm2Transposed<f32>,
 }
 
@@ -12,6 +20,8 @@ impl Noise {
         assert!(seed != 0);
         Self {
             rand: seed,
+            sample_rate,
+            frequency,
             filter: DirectForm2Transposed::<f32>::new(
                 Coefficients::<f32>::from_params(
                     biquad::Type::BandPass,
@@ -29,3 +39,85 @@ impl Noise {
         self.filter.run(x * 2.0 - 1.0)
     }
 }
+
+impl PartialEq for Noise {
+    fn eq(&self, other: &Self) -> bool {
+        self.rand == other.rand
+            && self.sample_rate == other.sample_rate
+            && self.frequency == other.frequency
+            && self.filter.s1 == other.filter.s1
+            && self.filter.s2 == other.filter.s2
+    }
+}
+
+/// The filter coefficients are not serializable, so they are recomputed from the parameters.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct NoiseState {
+    rand: u32,
+    sample_rate: f32,
+    frequency: f32,
+    s1: f32,
+    s2: f32,
+}
+
+#[cfg(feature = "serde")]
+impl From<Noise> for NoiseState {
+    fn from(noise: Noise) -> Self {
+        Self {
+            rand: noise.rand,
+            sample_rate: noise.sample_rate,
+            frequency: noise.frequency,
+            s1: noise.filter.s1,
+            s2: noise.filter.s2,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<NoiseState> for Noise {
+    fn from(state: NoiseState) -> Self {
+        let mut noise = Noise::new(state.rand.max(1), state.sample_rate, state.frequency);
+        noise.rand = state.rand;
+        noise.filter.s1 = state.s1;
+        noise.filter.s2 = state.s2;
+        noise
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    let mut noise = Noise::new(1, 48000.0, 1000.0);
+    for _ in 0..100 {
+        noise.process();
+    }
+    let mut restored: Noise =
+        serde_json::from_str(&serde_json::to_string(&noise).unwrap()).unwrap();
+    assert_eq!(restored, noise);
+    for _ in 0..100 {
+        assert_eq!(restored.process(), noise.process());
+    }
+
+    // A restored voice continues the same samples
+    let mut benihora = crate::Benihora::new(
+        3.0,
+        48000.0,
+        1.0,
+        0,
+        false,
+        &crate::tract::TractGeometry::default(),
+    );
+    for _ in 0..4800 {
+        benihora.process(140.0, 0.6, 1.0, 1.0, 1.0);
+    }
+    let json = serde_json::to_string(&benihora).unwrap();
+    let mut restored: crate::Benihora = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, benihora);
+    for _ in 0..4800 {
+        assert_eq!(
+            restored.process(140.0, 0.6, 1.0, 1.0, 1.0),
+            benihora.process(140.0, 0.6, 1.0, 1.0, 1.0)
+        );
+    }
+}
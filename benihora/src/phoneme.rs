@@ -0,0 +1,444 @@
+//! Phoneme sequence to tract/glottis automation.
+//!
+//! A phoneme string is a whitespace separated list of tokens in the form `PHONE[:duration][@pitch]`,
+//! e.g. `HH EH1:0.12@150 L OW:0.3@130 _:0.2`.
+//! Phones are ARPAbet symbols (stress digits are ignored) or a small IPA subset, `_` is silence.
+//! Durations are in seconds and pitches in Hz. Omitted pitches inherit the previous pitch.
+
+use crate::managed::BenihoraManaged;
+
+const DEFAULT_FREQUENCY: f32 = 140.0;
+const DEFAULT_TENSENESS: f32 = 0.6;
+const VELUM_CLOSED: f32 = 0.01;
+const VELUM_OPEN: f32 = 0.4;
+
+const LABIAL: f32 = 41.0;
+const DENTAL: f32 = 37.5;
+const ALVEOLAR: f32 = 36.0;
+const POSTALVEOLAR: f32 = 33.0;
+const VELAR: f32 = 25.0;
+
+const CLOSURE: f32 = 0.0;
+const FRICATION: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phone {
+    Silence,
+    /// Tongue targets and lip rounding. Diphthongs have a second target.
+    Vowel {
+        tongue: (f32, f32),
+        glide: Option<(f32, f32)>,
+        rounding: Option<f32>,
+    },
+    Stop {
+        place: f32,
+        voiced: bool,
+    },
+    Fricative {
+        place: f32,
+        voiced: bool,
+    },
+    Nasal {
+        place: f32,
+    },
+    Approximant {
+        place: f32,
+        diameter: f32,
+        tongue: (f32, f32),
+    },
+    Aspirate,
+}
+
+impl Phone {
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        if let Some(phone) = Self::from_ipa(symbol) {
+            return Some(phone);
+        }
+        let arpabet = symbol
+            .trim_end_matches(|c: char| c.is_ascii_digit())
+            .to_ascii_uppercase();
+        Self::from_arpabet(&arpabet)
+    }
+
+    fn from_arpabet(symbol: &str) -> Option<Self> {
+        use Phone::*;
+        Some(match symbol {
+            "_" | "SIL" | "SP" | "PAU" => Silence,
+            "IY" => vowel((27.2, 2.20), None),
+            "IH" => vowel((24.8, 2.65), None),
+            "EH" => vowel((19.4, 3.43), None),
+            "AE" => vowel((15.5, 3.1), None),
+            "AA" => vowel((12.9, 2.43), None),
+            "AH" | "AX" => vowel((17.5, 2.9), None),
+            "AO" => rounded((12.5, 2.2), 1.8),
+            "UH" => rounded((20.5, 2.4), 1.8),
+            "UW" => rounded((22.8, 2.05), 1.2),
+            "OW" => diphthong((14.0, 2.09), (22.8, 2.05), Some(1.5)),
+            "ER" => vowel((20.5, 2.8), None),
+            "EY" => diphthong((19.4, 3.43), (27.2, 2.20), None),
+            "AY" => diphthong((12.9, 2.43), (27.2, 2.20), None),
+            "AW" => diphthong((12.9, 2.43), (22.8, 2.05), Some(1.5)),
+            "OY" => diphthong((12.5, 2.2), (27.2, 2.20), Some(1.8)),
+            "P" => stop(LABIAL, false),
+            "B" => stop(LABIAL, true),
+            "T" => stop(ALVEOLAR, false),
+            "D" => stop(ALVEOLAR, true),
+            "K" => stop(VELAR, false),
+            "G" => stop(VELAR, true),
+            "CH" => stop(POSTALVEOLAR, false),
+            "JH" => stop(POSTALVEOLAR, true),
+            "F" => fricative(LABIAL, false),
+            "V" => fricative(LABIAL, true),
+            "TH" => fricative(DENTAL, false),
+            "DH" => fricative(DENTAL, true),
+            "S" => fricative(ALVEOLAR, false),
+            "Z" => fricative(ALVEOLAR, true),
+            "SH" => fricative(POSTALVEOLAR, false),
+            "ZH" => fricative(POSTALVEOLAR, true),
+            "M" => Nasal { place: LABIAL },
+            "N" => Nasal { place: ALVEOLAR },
+            "NG" => Nasal { place: VELAR },
+            "L" => approximant(ALVEOLAR, 0.9, (17.5, 2.9)),
+            "R" => approximant(POSTALVEOLAR, 1.2, (20.5, 2.8)),
+            "W" => approximant(LABIAL, 0.9, (22.8, 2.05)),
+            "Y" => approximant(29.0, 1.3, (27.2, 2.20)),
+            "HH" => Aspirate,
+            _ => return None,
+        })
+    }
+
+    fn from_ipa(symbol: &str) -> Option<Self> {
+        Self::from_arpabet(match symbol {
+            "i" => "IY",
+            "ɪ" => "IH",
+            "e" | "ɛ" => "EH",
+            "æ" => "AE",
+            "a" | "ɑ" => "AA",
+            "ə" | "ʌ" => "AH",
+            "ɔ" => "AO",
+            "o" => "OW",
+            "ʊ" => "UH",
+            "u" => "UW",
+            "p" => "P",
+            "b" => "B",
+            "t" => "T",
+            "d" => "D",
+            "k" => "K",
+            "g" | "ɡ" => "G",
+            "f" => "F",
+            "v" => "V",
+            "θ" => "TH",
+            "ð" => "DH",
+            "s" => "S",
+            "z" => "Z",
+            "ʃ" => "SH",
+            "ʒ" => "ZH",
+            "m" => "M",
+            "n" => "N",
+            "ŋ" => "NG",
+            "l" => "L",
+            "r" | "ɹ" => "R",
+            "w" => "W",
+            "j" => "Y",
+            "h" => "HH",
+            _ => return None,
+        })
+    }
+
+    pub fn default_duration(&self) -> f32 {
+        match self {
+            Phone::Silence => 0.1,
+            Phone::Vowel { glide: None, .. } => 0.15,
+            Phone::Vowel { .. } => 0.25,
+            Phone::Stop { .. } => 0.07,
+            Phone::Fricative { .. } => 0.1,
+            Phone::Nasal { .. } | Phone::Approximant { .. } | Phone::Aspirate => 0.08,
+        }
+    }
+}
+
+fn vowel(tongue: (f32, f32), rounding: Option<f32>) -> Phone {
+    Phone::Vowel {
+        tongue,
+        glide: None,
+        rounding,
+    }
+}
+
+fn rounded(tongue: (f32, f32), rounding: f32) -> Phone {
+    vowel(tongue, Some(rounding))
+}
+
+fn diphthong(tongue: (f32, f32), glide: (f32, f32), rounding: Option<f32>) -> Phone {
+    Phone::Vowel {
+        tongue,
+        glide: Some(glide),
+        rounding,
+    }
+}
+
+fn stop(place: f32, voiced: bool) -> Phone {
+    Phone::Stop { place, voiced }
+}
+
+fn fricative(place: f32, voiced: bool) -> Phone {
+    Phone::Fricative { place, voiced }
+}
+
+fn approximant(place: f32, diameter: f32, tongue: (f32, f32)) -> Phone {
+    Phone::Approximant {
+        place,
+        diameter,
+        tongue,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub phone: Phone,
+    pub duration: f32,
+    pub frequency: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    UnknownPhone(String),
+    InvalidNumber(String),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnknownPhone(s) => write!(f, "unknown phone: {}", s),
+            ParseError::InvalidNumber(s) => write!(f, "invalid number: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn parse(text: &str) -> Result<Vec<Note>, ParseError> {
+    text.split_whitespace().map(parse_token).collect()
+}
+
+fn parse_token(token: &str) -> Result<Note, ParseError> {
+    let (rest, frequency) = match token.split_once('@') {
+        Some((rest, frequency)) => (rest, Some(parse_frequency(frequency)?)),
+        None => (token, None),
+    };
+    let (symbol, duration) = match rest.split_once(':') {
+        Some((symbol, duration)) => (symbol, Some(parse_number(duration)?)),
+        None => (rest, None),
+    };
+    let phone =
+        Phone::from_symbol(symbol).ok_or_else(|| ParseError::UnknownPhone(symbol.to_owned()))?;
+    Ok(Note {
+        phone,
+        duration: duration.unwrap_or_else(|| phone.default_duration()),
+        frequency,
+    })
+}
+
+fn parse_number(s: &str) -> Result<f32, ParseError> {
+    s.parse()
+        .ok()
+        .filter(|x: &f32| x.is_finite() && *x >= 0.0)
+        .ok_or_else(|| ParseError::InvalidNumber(s.to_owned()))
+}
+
+/// A pitch in the range that `Frequency::set` takes
+fn parse_frequency(s: &str) -> Result<f32, ParseError> {
+    Some(parse_number(s)?)
+        .filter(|x| (1.0..=10000.0).contains(x))
+        .ok_or_else(|| ParseError::InvalidNumber(s.to_owned()))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlValue {
+    Tongue(f32, f32),
+    /// Replaces `ShapeSource::other_constrictions`
+    Constrictions(Vec<(f32, f32)>),
+    /// value: 0.01 - 0.4
+    Velum(f32),
+    Sound(bool),
+    Frequency(f32),
+    Tenseness(f32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Control {
+    pub time: f32,
+    pub value: ControlValue,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schedule {
+    /// Sorted by time
+    pub controls: Vec<Control>,
+    pub duration: f32,
+}
+
+impl Schedule {
+    pub fn from_notes(notes: &[Note]) -> Self {
+        let mut schedule = Schedule::default();
+        let mut time = 0.0;
+        let mut frequency = DEFAULT_FREQUENCY;
+
+        schedule.push(0.0, ControlValue::Frequency(frequency));
+        for note in notes {
+            if let Some(f) = note.frequency {
+                if f != frequency {
+                    frequency = f;
+                    schedule.push(time, ControlValue::Frequency(frequency));
+                }
+            }
+            schedule.push_phone(time, note.duration, &note.phone);
+            time += note.duration;
+        }
+        schedule.push(time, ControlValue::Sound(false));
+        schedule.duration = time;
+        schedule
+    }
+
+    fn push(&mut self, time: f32, value: ControlValue) {
+        self.controls.push(Control { time, value });
+    }
+
+    fn push_phone(&mut self, time: f32, duration: f32, phone: &Phone) {
+        use ControlValue::*;
+
+        let (sound, tenseness) = match phone {
+            Phone::Silence => (false, DEFAULT_TENSENESS),
+            Phone::Stop { voiced, .. } | Phone::Fricative { voiced, .. } => {
+                (*voiced, DEFAULT_TENSENESS)
+            }
+            Phone::Aspirate => (true, 0.0),
+            _ => (true, DEFAULT_TENSENESS),
+        };
+        self.push(time, Sound(sound));
+        self.push(time, Tenseness(tenseness));
+        self.push(
+            time,
+            Velum(if matches!(phone, Phone::Nasal { .. }) {
+                VELUM_OPEN
+            } else {
+                VELUM_CLOSED
+            }),
+        );
+
+        match *phone {
+            Phone::Silence | Phone::Aspirate => {
+                self.push(time, Constrictions(vec![]));
+            }
+            Phone::Vowel {
+                tongue,
+                glide,
+                rounding,
+            } => {
+                self.push(time, Tongue(tongue.0, tongue.1));
+                self.push(
+                    time,
+                    Constrictions(rounding.map(|d| vec![(LABIAL, d)]).unwrap_or_default()),
+                );
+                if let Some(glide) = glide {
+                    self.push(time + duration * 0.5, Tongue(glide.0, glide.1));
+                }
+            }
+            Phone::Stop { place, .. } | Phone::Nasal { place } => {
+                self.push(time, Constrictions(vec![(place, CLOSURE)]));
+            }
+            Phone::Fricative { place, .. } => {
+                self.push(time, Constrictions(vec![(place, FRICATION)]));
+            }
+            Phone::Approximant {
+                place,
+                diameter,
+                tongue,
+            } => {
+                self.push(time, Tongue(tongue.0, tongue.1));
+                self.push(time, Constrictions(vec![(place, diameter)]));
+            }
+        }
+    }
+
+    /// Renders the schedule and returns samples at `benihora.benihora.sample_rate`.
+    pub fn render(&self, benihora: &mut BenihoraManaged) -> Vec<f32> {
+        let sample_rate = benihora.benihora.sample_rate;
+        let length = (self.duration * sample_rate).ceil() as usize;
+        let mut buffer = Vec::with_capacity(length);
+        let mut controls = self.controls.iter().peekable();
+
+        for i in 0..length {
+            let time = i as f32 / sample_rate;
+            let mut shape_changed = false;
+            while let Some(control) = controls.next_if(|c| c.time <= time) {
+                shape_changed |= apply(benihora, &control.value);
+            }
+            if shape_changed {
+                benihora.benihora.tract.update_diameter();
+            }
+            buffer.push(benihora.process(time));
+        }
+
+        buffer
+    }
+}
+
+/// Returns true if the tract shape needs to be updated.
+fn apply(benihora: &mut BenihoraManaged, value: &ControlValue) -> bool {
+    let tract = &mut benihora.benihora.tract;
+    match value {
+        ControlValue::Tongue(index, diameter) => {
+            tract.source.tongue = tract.source.tongue_clamp(*index, *diameter);
+            true
+        }
+        ControlValue::Constrictions(constrictions) => {
+            tract.source.other_constrictions.clone_from(constrictions);
+            true
+        }
+        ControlValue::Velum(velum) => {
+            tract.set_velum_target(*velum);
+            false
+        }
+        ControlValue::Sound(sound) => {
+            benihora.sound = *sound;
+            false
+        }
+        ControlValue::Frequency(frequency) => {
+            benihora.frequency.set(*frequency);
+            false
+        }
+        ControlValue::Tenseness(tenseness) => {
+            benihora.set_tenseness(*tenseness);
+            false
+        }
+    }
+}
+
+/// Parses `text` and renders it through `benihora`.
+pub fn render(text: &str, benihora: &mut BenihoraManaged) -> Result<Vec<f32>, ParseError> {
+    let notes = parse(text)?;
+    Ok(Schedule::from_notes(&notes).render(benihora))
+}
+
+#[test]
+fn test_render() {
+    let notes = parse("HH EH1:0.12@150 L OW:0.3@130 _").unwrap();
+    assert_eq!(notes.len(), 5);
+    assert_eq!(notes[1].frequency, Some(150.0));
+    assert!(parse("XX").is_err());
+    assert_eq!(
+        parse("AA@0"),
+        Err(ParseError::InvalidNumber("0".to_owned()))
+    );
+    assert!(parse("AA@20000").is_err());
+
+    let schedule = Schedule::from_notes(&notes);
+    assert!(schedule.controls.windows(2).all(|w| w[0].time <= w[1].time));
+
+    let mut benihora = BenihoraManaged::new(3.0, 16000.0, 1);
+    let buffer = schedule.render(&mut benihora);
+    assert_eq!(buffer.len(), (schedule.duration * 16000.0).ceil() as usize);
+    assert!(buffer.iter().all(|x| x.is_finite()));
+    assert!(buffer.iter().any(|x| x.abs() > 0.01));
+}
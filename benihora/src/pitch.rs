@@ -0,0 +1,240 @@
+//! Pitch detection of a monophonic signal by the YIN algorithm (de Cheveigné and Kawahara 2002).
+
+use biquad::{Biquad, Coefficients, DirectForm2Transposed, ToHertz};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pitch {
+    /// Hz
+    pub frequency: f32,
+    /// 0..1, close to 1 for a clean periodic signal and low for noise or silence
+    pub confidence: f32,
+}
+
+/// Dips of the normalized difference below this are taken as the period
+/// before looking for a deeper dip at a longer lag
+const THRESHOLD: f32 = 0.15;
+
+/// Estimates the pitch of `signal` in `min_frequency..max_frequency`.
+/// `signal` should be longer than two periods of `min_frequency`.
+pub fn yin(
+    signal: &[f32],
+    sample_rate: f32,
+    min_frequency: f32,
+    max_frequency: f32,
+) -> Option<Pitch> {
+    let min_lag = ((sample_rate / max_frequency) as usize).max(2);
+    let max_lag = ((sample_rate / min_frequency).ceil() as usize).min(signal.len() / 2);
+    if max_lag < min_lag + 2 {
+        return None;
+    }
+    let width = signal.len() - max_lag - 1;
+
+    // Cumulative mean normalized difference function
+    let mut normalized = vec![1.0; max_lag + 2];
+    let mut sum = 0.0;
+    for (lag, value) in normalized.iter_mut().enumerate().skip(1) {
+        let difference: f32 = signal[..width]
+            .iter()
+            .zip(&signal[lag..lag + width])
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum();
+        sum += difference;
+        *value = if sum > 0.0 {
+            difference * lag as f32 / sum
+        } else {
+            1.0
+        };
+    }
+
+    // The first dip below the threshold, which avoids the multiples of the period,
+    // or the deepest one
+    let lag = (min_lag..=max_lag)
+        .find(|&lag| normalized[lag] < THRESHOLD)
+        .map(|mut lag| {
+            while lag < max_lag && normalized[lag + 1] < normalized[lag] {
+                lag += 1;
+            }
+            lag
+        })
+        .unwrap_or_else(|| {
+            (min_lag..=max_lag)
+                .min_by(|&a, &b| normalized[a].total_cmp(&normalized[b]))
+                .unwrap()
+        });
+
+    let (a, b, c) = (normalized[lag - 1], normalized[lag], normalized[lag + 1]);
+    let curvature = a - 2.0 * b + c;
+    let offset = if curvature > 0.0 {
+        (0.5 * (a - c) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+    Some(Pitch {
+        frequency: sample_rate / (lag as f32 + offset),
+        confidence: (1.0 - b).clamp(0.0, 1.0),
+    })
+}
+
+/// Runs `yin` on a sliding window of a stream, e.g. a microphone input.
+/// The input is decimated to about 16 kHz to keep the cost low at high sample rates.
+#[derive(Debug, Clone)]
+pub struct PitchTracker {
+    sample_rate: f32,
+    min_frequency: f32,
+    max_frequency: f32,
+    decimation: usize,
+    count: usize,
+    lowpass: [DirectForm2Transposed<f32>; 2],
+    window: Vec<f32>,
+    filled: usize,
+    hop: usize,
+    pitch: Option<Pitch>,
+}
+
+impl PitchTracker {
+    pub fn new(sample_rate: f32, min_frequency: f32, max_frequency: f32) -> Self {
+        assert!(0.0 < min_frequency && min_frequency < max_frequency);
+        let decimation = ((sample_rate / 16000.0) as usize).max(1);
+        let analysis_rate = sample_rate / decimation as f32;
+        let lowpass = DirectForm2Transposed::<f32>::new(
+            Coefficients::<f32>::from_params(
+                biquad::Type::LowPass,
+                sample_rate.hz(),
+                (analysis_rate * 0.4).hz(),
+                biquad::Q_BUTTERWORTH_F32,
+            )
+            .unwrap(),
+        );
+        let window_length = 2 * (analysis_rate / min_frequency).ceil() as usize + 2;
+        Self {
+            sample_rate: analysis_rate,
+            min_frequency,
+            max_frequency,
+            decimation,
+            count: 0,
+            lowpass: [lowpass, lowpass],
+            window: vec![0.0; window_length],
+            filled: 0,
+            // Every 5 ms, or every window if it is shorter
+            hop: ((analysis_rate * 0.005) as usize).clamp(1, window_length),
+            pitch: None,
+        }
+    }
+
+    /// Feeds a sample and returns the latest estimate.
+    /// The estimate is updated every 5 ms and delayed by the window length.
+    pub fn process(&mut self, x: f32) -> Option<Pitch> {
+        let x = self.lowpass[0].run(x);
+        let x = self.lowpass[1].run(x);
+        self.count += 1;
+        if self.count < self.decimation {
+            return self.pitch;
+        }
+        self.count = 0;
+
+        let length = self.window.len();
+        self.window[self.filled] = x;
+        self.filled += 1;
+        if self.filled == length {
+            self.pitch = yin(
+                &self.window,
+                self.sample_rate,
+                self.min_frequency,
+                self.max_frequency,
+            );
+            self.window.copy_within(self.hop.., 0);
+            self.filled = length - self.hop;
+        }
+        self.pitch
+    }
+
+    pub fn pitch(&self) -> Option<Pitch> {
+        self.pitch
+    }
+
+    /// Delay of the estimate in seconds
+    pub fn latency(&self) -> f32 {
+        self.window.len() as f32 / self.sample_rate
+    }
+}
+
+#[cfg(test)]
+fn harmonic_signal(frequency: f32, sample_rate: f32, length: usize) -> Vec<f32> {
+    // A sawtooth-like spectrum with a weak fundamental is a harder case than a sine
+    (0..length)
+        .map(|i| {
+            let t = i as f32 / sample_rate;
+            (1..10)
+                .map(|k| {
+                    let amplitude = if k == 1 { 0.3 } else { 1.0 / k as f32 };
+                    amplitude * (std::f32::consts::TAU * frequency * k as f32 * t).sin()
+                })
+                .sum::<f32>()
+                * 0.3
+        })
+        .collect()
+}
+
+#[test]
+fn test_yin() {
+    let sample_rate = 16000.0;
+    for frequency in [82.0, 110.0, 196.0, 261.6, 440.0, 880.0] {
+        let signal = harmonic_signal(frequency, sample_rate, 1024);
+        let pitch = yin(&signal, sample_rate, 60.0, 1000.0).unwrap();
+        assert!(
+            (pitch.frequency / frequency - 1.0).abs() < 0.005,
+            "{} {:?}",
+            frequency,
+            pitch
+        );
+        assert!(0.9 < pitch.confidence, "{} {:?}", frequency, pitch);
+    }
+
+    // Noise and silence are not confident
+    let mut seed = 1;
+    let noise: Vec<f32> = (0..1024)
+        .map(|_| crate::rand_f32(&mut seed) * 2.0 - 1.0)
+        .collect();
+    assert!(yin(&noise, sample_rate, 60.0, 1000.0).unwrap().confidence < 0.5);
+    assert_eq!(
+        yin(&[0.0; 1024], sample_rate, 60.0, 1000.0)
+            .unwrap()
+            .confidence,
+        0.0
+    );
+}
+
+#[test]
+fn test_pitch_tracker() {
+    let sample_rate = 48000.0;
+    let mut tracker = PitchTracker::new(sample_rate, 60.0, 1000.0);
+    let n = (sample_rate * 0.2) as usize;
+
+    // Follows a step from 150 Hz to 300 Hz within the latency
+    let mut signal = harmonic_signal(150.0, sample_rate, n);
+    signal.extend(harmonic_signal(300.0, sample_rate, n));
+    let estimates: Vec<_> = signal.iter().map(|&x| tracker.process(x)).collect();
+    let latency = (tracker.latency() * sample_rate) as usize;
+    for (range, frequency) in [(latency..n, 150.0), (n + latency..2 * n, 300.0)] {
+        for pitch in &estimates[range] {
+            let pitch = pitch.unwrap();
+            assert!(
+                (pitch.frequency / frequency - 1.0).abs() < 0.01,
+                "{:?}",
+                pitch
+            );
+            assert!(0.9 < pitch.confidence);
+        }
+    }
+
+    for _ in 0..n {
+        tracker.process(0.0);
+    }
+    assert!(tracker.pitch().unwrap().confidence < 0.5);
+
+    // The window can be shorter than the hop
+    let mut tracker = PitchTracker::new(sample_rate, 4000.0, 6000.0);
+    for x in harmonic_signal(4400.0, sample_rate, 4800) {
+        tracker.process(x);
+    }
+}
@@ -1,41 +1,68 @@
-#[derive(Debug, Clone)]
-pub enum Algo {
+use std::ops::{Add, Mul, Sub};
+
+/// A sample that can be resampled, e.g. `f32` or a set of channels
+pub trait Frame:
+    Copy + Default + Add<Output = Self> + Sub<Output = Self> + Mul<f32, Output = Self>
+{
+}
+
+impl Frame for f32 {}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Algo<T = f32> {
     UpSample {
         in_per_out: f32,
-        prev_sample: f32,
-        next_sample: f32,
+        prev_sample: T,
+        next_sample: T,
         next_sample_time: f32,
     },
     DownSample {
         in_per_out: f32,
         out_per_in: f32,
-        left_value: f32,
-        right_value: f32,
+        left_value: T,
+        right_value: T,
+        time: f32,
+    },
+    /// Windowed-sinc interpolation
+    Sinc {
+        in_per_out: f32,
+        /// Taps on each side of the output point
+        half_width: usize,
+        /// Kernel sampled `SINC_TABLE_RESOLUTION` times per input sample from the center
+        table: Vec<f32>,
+        /// Ring buffer of the last `2 * half_width` input samples, stored twice to read it as a slice
+        history: Vec<T>,
+        position: usize,
         time: f32,
     },
     Identity,
 }
 
-pub struct Resample {
-    algo: Algo,
+const SINC_TABLE_RESOLUTION: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Resample<T = f32> {
+    algo: Algo<T>,
 }
 
-impl Resample {
+impl<T: Frame> Resample<T> {
     pub fn new(input_sample_rate: f32, output_sample_rate: f32) -> Self {
         Self {
             algo: if input_sample_rate < output_sample_rate {
                 Algo::UpSample {
                     in_per_out: input_sample_rate / output_sample_rate,
-                    prev_sample: 0.0,
-                    next_sample: 0.0,
+                    prev_sample: T::default(),
+                    next_sample: T::default(),
                     next_sample_time: 1.0,
                 }
             } else if input_sample_rate > output_sample_rate {
                 Algo::DownSample {
                     in_per_out: input_sample_rate / output_sample_rate,
                     out_per_in: output_sample_rate / input_sample_rate,
-                    left_value: 0.0,
-                    right_value: 0.0,
+                    left_value: T::default(),
+                    right_value: T::default(),
                     time: 0.0,
                 }
             } else {
@@ -44,7 +71,69 @@ impl Resample {
         }
     }
 
-    pub fn process(&mut self, mut x: impl FnMut() -> f32) -> f32 {
+    /// Band-limited resampler with a Blackman-windowed sinc kernel.
+    /// The kernel has `zero_crossings` zero crossings on each side. Larger values give a sharper
+    /// cutoff and a wider passband (about 50% of the lower Nyquist frequency at 8, 84% at 32),
+    /// at the cost of CPU time and `latency`.
+    pub fn new_sinc(
+        input_sample_rate: f32,
+        output_sample_rate: f32,
+        zero_crossings: usize,
+    ) -> Self {
+        assert!(zero_crossings > 0);
+        if input_sample_rate == output_sample_rate {
+            return Self {
+                algo: Algo::Identity,
+            };
+        }
+
+        // Cutoff relative to the input Nyquist frequency.
+        // The transition band of the Blackman window is about 5.5 / taps wide,
+        // so the cutoff is lowered to end the transition band at the lower Nyquist frequency.
+        let cutoff = (output_sample_rate / input_sample_rate).min(1.0)
+            / (1.0 + 2.75 / zero_crossings as f32);
+        let half_width = (zero_crossings as f32 / cutoff).ceil() as usize;
+        let table = (0..=half_width * SINC_TABLE_RESOLUTION + 1)
+            .map(|i| {
+                let d = i as f32 / SINC_TABLE_RESOLUTION as f32;
+                if d >= half_width as f32 {
+                    return 0.0;
+                }
+                let x = std::f32::consts::PI * cutoff * d;
+                let sinc = if x == 0.0 { 1.0 } else { x.sin() / x };
+                let w = std::f32::consts::PI * (d / half_width as f32 + 1.0);
+                let window = 0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos();
+                cutoff * sinc * window
+            })
+            .collect();
+
+        Self {
+            algo: Algo::Sinc {
+                in_per_out: input_sample_rate / output_sample_rate,
+                half_width,
+                table,
+                history: vec![T::default(); half_width * 4],
+                position: 0,
+                time: 0.0,
+            },
+        }
+    }
+
+    /// Approximate delay in output samples.
+    pub fn latency(&self) -> f32 {
+        match self.algo {
+            Algo::UpSample { .. } => 0.0,
+            Algo::DownSample { .. } => 1.5,
+            Algo::Sinc {
+                in_per_out,
+                half_width,
+                ..
+            } => (half_width as f32 - 0.5) / in_per_out,
+            Algo::Identity => 0.0,
+        }
+    }
+
+    pub fn process(&mut self, mut x: impl FnMut() -> T) -> T {
         match self.algo {
             Algo::UpSample {
                 in_per_out,
@@ -81,7 +170,109 @@ impl Resample {
                 *time = *time - 1.0;
                 y * out_per_in
             }
+            Algo::Sinc {
+                in_per_out,
+                half_width,
+                ref table,
+                ref mut history,
+                ref mut position,
+                ref mut time,
+            } => {
+                *time += in_per_out;
+                while 1.0 <= *time {
+                    *time -= 1.0;
+                    let x = x();
+                    history[*position] = x;
+                    history[*position + half_width * 2] = x;
+                    *position = (*position + 1) % (half_width * 2);
+                }
+
+                // The output point is `time` after the center of the history
+                let samples = &history[*position..*position + half_width * 2];
+                let mut y = T::default();
+                for (j, &x) in samples.iter().enumerate() {
+                    let d = (j as f32 - (half_width - 1) as f32 - *time).abs()
+                        * SINC_TABLE_RESOLUTION as f32;
+                    let i = d as usize;
+                    let k = crate::lerp(table[i], table[i + 1], d - i as f32);
+                    y = y + x * k;
+                }
+                y
+            }
             Algo::Identity => x(),
         }
     }
 }
+
+#[test]
+fn test_sinc_aliasing() {
+    // Measures the level of the output at the frequency of the alias of a tone
+    // above the output Nyquist frequency, and of a tone in the passband.
+    fn level(resample: &mut Resample, input_sample_rate: f32, frequency: f32, probe: f32) -> f32 {
+        let output_sample_rate = 44100.0;
+        let mut i = 0;
+        let mut output = Vec::new();
+        for _ in 0..8192 {
+            output.push(resample.process(|| {
+                i += 1;
+                (std::f32::consts::TAU * frequency * i as f32 / input_sample_rate).sin()
+            }));
+        }
+        let output = &output[1024..];
+        let (mut re, mut im) = (0.0, 0.0);
+        for (n, y) in output.iter().enumerate() {
+            let w = std::f32::consts::TAU * probe * n as f32 / output_sample_rate;
+            let window = 0.5 - 0.5 * (std::f32::consts::TAU * n as f32 / output.len() as f32).cos();
+            re += y * window * w.cos();
+            im += y * window * w.sin();
+        }
+        20.0 * (re.hypot(im) * 4.0 / output.len() as f32).log10()
+    }
+
+    let input_sample_rate = 48000.0 * 6.0;
+    for frequency in [26000.0, 30000.0, 40000.0, 60000.0] {
+        let alias: f32 = frequency % 44100.0;
+        let alias = alias.min(44100.0 - alias);
+        let linear = level(
+            &mut Resample::new(input_sample_rate, 44100.0),
+            input_sample_rate,
+            frequency,
+            alias,
+        );
+        let low = level(
+            &mut Resample::new_sinc(input_sample_rate, 44100.0, 8),
+            input_sample_rate,
+            frequency,
+            alias,
+        );
+        let high = level(
+            &mut Resample::new_sinc(input_sample_rate, 44100.0, 32),
+            input_sample_rate,
+            frequency,
+            alias,
+        );
+        assert!(low < linear - 20.0, "{} {} {}", frequency, low, linear);
+        assert!(high < -70.0, "{} {}", frequency, high);
+    }
+
+    for zero_crossings in [8, 32] {
+        let passband = level(
+            &mut Resample::new_sinc(input_sample_rate, 44100.0, zero_crossings),
+            input_sample_rate,
+            1000.0,
+            1000.0,
+        );
+        assert!(passband.abs() < 0.5, "{}", passband);
+    }
+    let passband = level(
+        &mut Resample::new_sinc(16000.0, 44100.0, 8),
+        16000.0,
+        1000.0,
+        1000.0,
+    );
+    assert!(passband.abs() < 0.5, "{}", passband);
+
+    let low = Resample::<f32>::new_sinc(input_sample_rate, 44100.0, 8);
+    let high = Resample::<f32>::new_sinc(input_sample_rate, 44100.0, 32);
+    assert!(low.latency() < high.latency());
+}
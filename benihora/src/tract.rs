@@ -1,11 +1,16 @@
 use std::f32::consts::PI;
 
-use crate::{lerp, noise::Noise, IntervalTimer};
+use crate::{articulator::Articulators, lerp, noise::Noise, IntervalTimer};
 
+/// Segment count of the mouth of the default tract, which the default shapes are made for
+pub const DEFAULT_MOUTH_LENGTH: usize = 44;
+/// Tongue of the default tract. `ShapeSource::scale_index` maps it to other tracts.
 pub const DEFAULT_TONGUE: (f32, f32) = (12.9, 2.43);
 
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tract {
-    pub(crate) params: OtherParams,
+    pub params: OtherParams,
     pub source: ShapeSource,
     pub current_diameter: Diameter,
     pub target_diameter: Diameter,
@@ -13,6 +18,8 @@ pub struct Tract {
     pub(crate) new_reflections: Reflections,
     pub state: State,
     pub movement_speed: f32, // CM per second
+    movement: Movement,
+    velocity: Diameter,
     sample_rate: f32,
     update_timer: IntervalTimer,
     fricative_noise: Noise,
@@ -22,12 +29,21 @@ pub struct Tract {
 }
 
 impl Tract {
-    pub fn new(steps_per_process: usize, sample_rate: f32, seed: u32) -> Self {
-        let mouth_length = 44;
-        let nose_length = 28;
-        let nose_start = mouth_length - nose_length + 1;
-        let source = ShapeSource::new(mouth_length, nose_length);
-        let mut diameter = Diameter::new(&source);
+    pub fn new(
+        steps_per_process: usize,
+        sample_rate: f32,
+        seed: u32,
+        geometry: &TractGeometry,
+    ) -> Self {
+        geometry.validate();
+        let mouth_length = geometry.mouth_length;
+        let nose_length = geometry.nose_length;
+        let nose_start = geometry.nose_start();
+        let source = ShapeSource::new(geometry);
+        let mut diameter = Diameter::new(geometry);
+        let mut velocity = diameter.clone();
+        velocity.mouth.fill(0.0);
+        velocity.nose.fill(0.0);
         let mut reflections = Reflections::new(mouth_length, nose_length);
         source.compute_diameter(&mut diameter);
         diameter.compute_reflections(&mut reflections);
@@ -41,6 +57,8 @@ impl Tract {
             new_reflections: reflections.clone(),
             state: State::new(mouth_length, nose_length),
             movement_speed: 15.0,
+            movement: Movement::Linear,
+            velocity,
             sample_rate,
             update_timer: IntervalTimer::new_overflowed(0.02),
             fricative_noise: Noise::new(seed + 1, sample_rate, 1000.0),
@@ -51,6 +69,26 @@ impl Tract {
     }
 
     pub fn process(&mut self, intensity: f32, x: f32) -> f32 {
+        let mut vocal_out = 0.0;
+        self.process_steps(intensity, x, |mouth, nose| vocal_out += mouth + nose);
+
+        (vocal_out / self.steps_per_process as f32).into()
+    }
+
+    /// Returns the lip output and the nose output separately.
+    pub fn process_split(&mut self, intensity: f32, x: f32) -> (f32, f32) {
+        let mut mouth_out = 0.0;
+        let mut nose_out = 0.0;
+        self.process_steps(intensity, x, |mouth, nose| {
+            mouth_out += mouth;
+            nose_out += nose;
+        });
+
+        let steps = self.steps_per_process as f32;
+        (mouth_out / steps, nose_out / steps)
+    }
+
+    fn process_steps(&mut self, intensity: f32, x: f32, mut output: impl FnMut(f32, f32)) {
         if self.update_timer.overflowed() {
             self.update_block(self.update_timer.interval);
         }
@@ -63,13 +101,10 @@ impl Tract {
         let x = x + fricative_noise * 1.0e-16;
 
         let turbulence_noise = fricative_noise * intensity;
-        let mut vocal_out = 0.0;
         for _ in 0..self.steps_per_process {
             let (mouth, nose) = self.run_step(x, turbulence_noise, lambda);
-            vocal_out += mouth + nose;
+            output(mouth, nose);
         }
-
-        (vocal_out / self.steps_per_process as f32).into()
     }
 
     pub fn run_step(
@@ -103,8 +138,17 @@ impl Tract {
     }
 
     pub fn update_block(&mut self, block_time: f32) {
-        self.current_diameter
-            .reshape(&self.target_diameter, block_time * self.movement_speed);
+        match &self.movement {
+            Movement::Linear => self
+                .current_diameter
+                .reshape(&self.target_diameter, block_time * self.movement_speed),
+            Movement::Spring(articulators) => self.current_diameter.reshape_spring(
+                &self.target_diameter,
+                &mut self.velocity,
+                articulators,
+                block_time,
+            ),
+        }
         {
             let mut new_last_obstruction = usize::MAX; // indicates whether it is an occlusion
             for (i, d) in self.current_diameter.mouth.iter().enumerate() {
@@ -129,6 +173,21 @@ impl Tract {
         std::mem::swap(&mut self.reflections, &mut self.new_reflections);
         self.current_diameter
             .compute_reflections(&mut self.new_reflections);
+        if self.params.losses != Losses::Uniform {
+            self.current_diameter.compute_losses(
+                &self.params.losses,
+                self.params.sample_rate,
+                &mut self.new_reflections,
+            );
+        }
+    }
+
+    pub fn set_losses(&mut self, losses: Losses) {
+        self.params.losses = losses;
+        for reflections in [&mut self.reflections, &mut self.new_reflections] {
+            self.current_diameter
+                .compute_losses(&losses, self.params.sample_rate, reflections);
+        }
     }
 
     pub fn update_diameter(&mut self) {
@@ -157,6 +216,21 @@ impl Tract {
         }
     }
 
+    pub fn movement(&self) -> &Movement {
+        &self.movement
+    }
+
+    pub fn set_movement(&mut self, movement: Movement) {
+        if !matches!(
+            (&self.movement, &movement),
+            (Movement::Spring(_), Movement::Spring(_))
+        ) {
+            self.velocity.mouth.fill(0.0);
+            self.velocity.nose.fill(0.0);
+        }
+        self.movement = movement;
+    }
+
     pub fn velum_target(&self) -> f32 {
         self.target_diameter.nose[0]
     }
@@ -167,6 +241,8 @@ impl Tract {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ShapeSource {
     pub length: usize,
     pub nose_length: usize,
@@ -176,36 +252,40 @@ pub struct ShapeSource {
     pub nose_start: usize,
 
     original_diameter: Vec<f32>,
+    /// Mouth diameters of a measured shape, see `AreaFunction::apply`.
+    /// It replaces the rest shape and the tongue while set, and the constrictions still apply.
+    pub measured_diameter: Option<Vec<f32>>,
 
     pub tongue: (f32, f32), // (index, diameter) // TODO index -> rate, should this be here?
     pub other_constrictions: Vec<(f32, f32)>,
 }
 
 impl ShapeSource {
-    pub fn new(length: usize, nose_length: usize) -> Self {
-        let original_diameter = (0..length)
-            .map(|i| {
-                if (i as f32) < (7.0 / 44.0 * length as f32 - 0.5) {
-                    0.6
-                } else if (i as f32) < (12.0 / 44.0 * length as f32) {
-                    1.1
-                } else {
-                    1.5
-                }
-            })
-            .collect();
-
-        ShapeSource {
-            length,
-            nose_length,
-            blade_start: (10.0 / 44.0 * length as f32).floor() as usize,
-            tip_start: (32.0 / 44.0 * length as f32).floor() as usize,
-            lip_start: (39.0 / 44.0 * length as f32).floor() as usize,
-            nose_start: length - nose_length + 1,
-            original_diameter,
+    pub fn new(geometry: &TractGeometry) -> Self {
+        let mut source = ShapeSource {
+            length: geometry.mouth_length,
+            nose_length: geometry.nose_length,
+            blade_start: geometry.blade_start,
+            tip_start: geometry.tip_start,
+            lip_start: geometry.lip_start,
+            nose_start: geometry.nose_start(),
+            original_diameter: geometry.rest_diameter.clone(),
+            measured_diameter: None,
             tongue: DEFAULT_TONGUE,
             other_constrictions: Vec::new(),
-        }
+        };
+        source.tongue.0 = source.scale_index(DEFAULT_TONGUE.0);
+        source
+    }
+
+    /// Maps an index of the default 44-segment tract to the same place of this tract.
+    pub fn scale_index(&self, index: f32) -> f32 {
+        index * (self.length as f32 / DEFAULT_MOUTH_LENGTH as f32)
+    }
+
+    /// Inverse of `scale_index`
+    pub fn unscale_index(&self, index: f32) -> f32 {
+        index * (DEFAULT_MOUTH_LENGTH as f32 / self.length as f32)
     }
 
     pub fn compute_diameter(&self, diameter: &mut Diameter) {
@@ -213,19 +293,23 @@ impl ShapeSource {
 
         let (tongue_index, tongue_diameter) = self.tongue;
 
-        diameter.mouth.copy_from_slice(&self.original_diameter);
-        for i in self.blade_start..self.lip_start {
-            let t =
-                1.1 * PI * (tongue_index - i as f32) / (self.tip_start - self.blade_start) as f32;
-            let fixed_tongue_diameter = 2.0 + (tongue_diameter - 2.0) / 1.5;
-            let mut curve = (1.5 - fixed_tongue_diameter + GRID_OFFSET) * t.cos();
-            if i == self.blade_start - 2 || i == self.lip_start - 1 {
-                curve *= 0.8;
-            }
-            if i == self.blade_start || i == self.lip_start - 2 {
-                curve *= 0.94;
+        if let Some(measured_diameter) = &self.measured_diameter {
+            diameter.mouth.copy_from_slice(measured_diameter);
+        } else {
+            diameter.mouth.copy_from_slice(&self.original_diameter);
+            for i in self.blade_start..self.lip_start {
+                let t = 1.1 * PI * (tongue_index - i as f32)
+                    / (self.tip_start - self.blade_start) as f32;
+                let fixed_tongue_diameter = 2.0 + (tongue_diameter - 2.0) / 1.5;
+                let mut curve = (1.5 - fixed_tongue_diameter + GRID_OFFSET) * t.cos();
+                if i == self.blade_start - 2 || i == self.lip_start - 1 {
+                    curve *= 0.8;
+                }
+                if i == self.blade_start || i == self.lip_start - 2 {
+                    curve *= 0.94;
+                }
+                diameter.mouth[i] = 1.5 - curve;
             }
-            diameter.mouth[i] = 1.5 - curve;
         }
 
         for constriction in self.other_constrictions.iter() {
@@ -233,13 +317,14 @@ impl ShapeSource {
             let mut d = constriction.1;
             d = (d - 0.3).max(0.0);
 
-            let width = if index < 25.0 {
+            let width_start = self.scale_index(25.0);
+            let width = self.scale_index(if index < width_start {
                 10.0
             } else if index >= self.tip_start as f32 {
                 5.0
             } else {
-                10.0 - 5.0 * (index - 25.0) / (self.tip_start as f32 - 25.0)
-            };
+                10.0 - 5.0 * (index - width_start) / (self.tip_start as f32 - width_start)
+            });
 
             if index >= 2.0 && index < self.length as f32 && d < 3.0 {
                 // && y<tractCanvas.height
@@ -286,32 +371,100 @@ impl ShapeSource {
     }
 }
 
-#[derive(Clone)]
-pub struct Diameter {
-    nose_start: usize,
-    tip_start: usize,
-    pub mouth: Vec<f32>,
-    pub nose: Vec<f32>,
+/// Segment counts, rest shapes and landmark positions of the vocal tract.
+/// The default models the original Pink Trombone tract.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TractGeometry {
+    pub mouth_length: usize,
+    pub nose_length: usize,
+    pub blade_start: usize,
+    pub tip_start: usize,
+    pub lip_start: usize,
+    /// Diameter of each mouth segment at rest, from the glottis to the lips
+    pub rest_diameter: Vec<f32>,
+    /// Diameter of each nose segment, from the velum to the nostrils.
+    /// The first segment is the velum and is closed initially.
+    pub nose_diameter: Vec<f32>,
 }
 
-impl Diameter {
-    pub fn new(source: &ShapeSource) -> Self {
-        let mut nose: Vec<_> = (0..source.nose_length)
+impl TractGeometry {
+    /// Scales the default shape to the given segment counts.
+    pub fn new(mouth_length: usize, nose_length: usize) -> Self {
+        let rest_diameter = (0..mouth_length)
+            .map(|i| {
+                if (i as f32) < (7.0 / 44.0 * mouth_length as f32 - 0.5) {
+                    0.6
+                } else if (i as f32) < (12.0 / 44.0 * mouth_length as f32) {
+                    1.1
+                } else {
+                    1.5
+                }
+            })
+            .collect();
+
+        let nose_diameter = (0..nose_length)
             .map(|i| {
-                let d = 2.0 * i as f32 / source.nose_length as f32;
-                (1.9 as f32).min(if d < 1.0 {
+                let d = 2.0 * i as f32 / nose_length as f32;
+                (1.9f32).min(if d < 1.0 {
                     0.4 + 1.6 * d
                 } else {
                     0.5 + 1.5 * (2.0 - d)
                 })
             })
             .collect();
+
+        TractGeometry {
+            mouth_length,
+            nose_length,
+            blade_start: (10.0 / 44.0 * mouth_length as f32).floor() as usize,
+            tip_start: (32.0 / 44.0 * mouth_length as f32).floor() as usize,
+            lip_start: (39.0 / 44.0 * mouth_length as f32).floor() as usize,
+            rest_diameter,
+            nose_diameter,
+        }
+    }
+
+    pub fn nose_start(&self) -> usize {
+        self.mouth_length - self.nose_length + 1
+    }
+
+    fn validate(&self) {
+        assert!(3 <= self.nose_length && self.nose_length < self.mouth_length - 1);
+        assert!(2 <= self.blade_start);
+        assert!(self.blade_start < self.tip_start);
+        assert!(self.tip_start < self.lip_start);
+        assert!(self.lip_start <= self.mouth_length);
+        assert_eq!(self.rest_diameter.len(), self.mouth_length);
+        assert_eq!(self.nose_diameter.len(), self.nose_length);
+    }
+}
+
+impl Default for TractGeometry {
+    fn default() -> Self {
+        Self::new(DEFAULT_MOUTH_LENGTH, 28)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diameter {
+    nose_start: usize,
+    tip_start: usize,
+    lip_start: usize,
+    pub mouth: Vec<f32>,
+    pub nose: Vec<f32>,
+}
+
+impl Diameter {
+    pub fn new(geometry: &TractGeometry) -> Self {
+        let mut nose = geometry.nose_diameter.clone();
         nose[0] = 0.01; // velum
 
         Diameter {
-            nose_start: source.nose_start,
-            tip_start: source.tip_start,
-            mouth: vec![0.0; source.length],
+            nose_start: geometry.nose_start(),
+            tip_start: geometry.tip_start,
+            lip_start: geometry.lip_start,
+            mouth: vec![0.0; geometry.mouth_length],
             nose,
         }
     }
@@ -343,6 +496,51 @@ impl Diameter {
         );
     }
 
+    /// Moves the sections toward the target by the springs of the articulators.
+    /// `velocity` holds the velocities of the sections and has the same shape as `self`.
+    pub fn reshape_spring(
+        &mut self,
+        target_diameter: &Diameter,
+        velocity: &mut Diameter,
+        articulators: &Articulators,
+        dtime: f32,
+    ) {
+        for i in 0..self.mouth.len() {
+            let spring = if i < self.tip_start {
+                &articulators.tongue
+            } else if i < self.lip_start {
+                &articulators.tip
+            } else {
+                &articulators.lips
+            };
+            // Aim past the wall so that a closure is reached in finite time
+            // even by a critically damped spring
+            let target = target_diameter.mouth[i];
+            let target = if target <= 0.0 {
+                -CLOSURE_OVERSHOOT
+            } else {
+                target
+            };
+            spring.update(&mut self.mouth[i], &mut velocity.mouth[i], target, dtime);
+            if self.mouth[i] <= 0.0 {
+                // The walls collide and stop
+                self.mouth[i] = 0.0;
+                velocity.mouth[i] = velocity.mouth[i].max(0.0);
+            }
+        }
+
+        articulators.velum.update(
+            &mut self.nose[0],
+            &mut velocity.nose[0],
+            target_diameter.nose[0],
+            dtime,
+        );
+        if self.nose[0] < 0.0 {
+            self.nose[0] = 0.0;
+            velocity.nose[0] = velocity.nose[0].max(0.0);
+        }
+    }
+
     pub fn compute_reflections(&mut self, reflections: &mut Reflections) {
         let area: Vec<_> = self.mouth.iter().map(|d| d * d).collect();
         for i in 0..self.mouth.len() - 1 {
@@ -364,9 +562,38 @@ impl Diameter {
         reflections.junction_right = 2.0 * area[self.nose_start + 1] / sum - 1.0;
         reflections.junction_nose = 2.0 * nose_area[0] / sum - 1.0;
     }
+
+    /// step_rate: number of steps per second
+    pub fn compute_losses(&self, losses: &Losses, step_rate: f32, reflections: &mut Reflections) {
+        let Losses::Physical { wall, viscous } = *losses else {
+            reflections.mouth_losses.fill(SectionLoss::NONE);
+            reflections.nose_losses.fill(SectionLoss::NONE);
+            return;
+        };
+
+        // A section loses energy through its perimeter, so the decay rate is proportional to
+        // perimeter / area, which is 4 / diameter for a circular section.
+        // The lowpass of a section attenuates by about 1 - lowpass * w^2 / 2 per step,
+        // which is fitted to the viscous decay rate at 1 kHz.
+        let w = 2.0 * PI * 1000.0 / step_rate;
+        let section_loss = |diameter: &f32| {
+            let diameter = diameter.max(MIN_LOSS_DIAMETER);
+            SectionLoss {
+                gain: (-wall / (diameter * step_rate)).exp(),
+                lowpass: (2.0 * viscous / (diameter * step_rate * w * w)).min(0.5),
+            }
+        };
+        for (loss, diameter) in reflections.mouth_losses.iter_mut().zip(&self.mouth) {
+            *loss = section_loss(diameter);
+        }
+        for (loss, diameter) in reflections.nose_losses.iter_mut().zip(&self.nose) {
+            *loss = section_loss(diameter);
+        }
+    }
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Reflections {
     mouth: Vec<f32>,
     pub(crate) nose: Vec<f32>,
@@ -374,6 +601,26 @@ pub struct Reflections {
     junction_left: f32,
     junction_right: f32,
     junction_nose: f32,
+
+    mouth_losses: Vec<SectionLoss>,
+    nose_losses: Vec<SectionLoss>,
+}
+
+/// Loss of a waveguide section in `Losses::Physical`
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct SectionLoss {
+    /// Frequency-independent gain per step
+    gain: f32,
+    /// Amount of the two-tap lowpass per step, 0: flat, 0.5: zero at Nyquist
+    lowpass: f32,
+}
+
+impl SectionLoss {
+    const NONE: Self = Self {
+        gain: 1.0,
+        lowpass: 0.0,
+    };
 }
 
 impl Reflections {
@@ -385,10 +632,15 @@ impl Reflections {
             junction_left: 0.0,
             junction_right: 0.0,
             junction_nose: 0.0,
+
+            mouth_losses: vec![SectionLoss::NONE; length],
+            nose_losses: vec![SectionLoss::NONE; nose_length],
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct State {
     r: Vec<f32>,
     l: Vec<f32>,
@@ -400,8 +652,18 @@ pub struct State {
     nose_r_: Vec<f32>,
     nose_l_: Vec<f32>,
 
+    // Waves of the previous step before the losses, used by `Losses::Physical`
+    last_r: Vec<f32>,
+    last_l: Vec<f32>,
+    last_nose_r: Vec<f32>,
+    last_nose_l: Vec<f32>,
+
     transients: Vec<Transient>,
     turbulences: Vec<Turbulence>,
+
+    // States of the radiation filters
+    lip_lowpass: f32,
+    nose_lowpass: f32,
 }
 
 impl State {
@@ -417,8 +679,16 @@ impl State {
             nose_r_: vec![0.0; nose_length],
             nose_l_: vec![0.0; nose_length],
 
+            last_r: vec![0.0; length],
+            last_l: vec![0.0; length],
+            last_nose_r: vec![0.0; nose_length],
+            last_nose_l: vec![0.0; nose_length],
+
             transients: Vec::new(),
             turbulences: Vec::new(),
+
+            lip_lowpass: 0.0,
+            nose_lowpass: 0.0,
         }
     }
 
@@ -465,9 +735,18 @@ impl State {
     ) -> f32 {
         let length = self.r.len();
 
-        //self.glottalReflection = -0.8 + 1.6 * Glottis.newTenseness;
         self.r_[0] = self.l[0] * params.glottal_reflection + glottal_output;
-        self.l_[length - 1] = self.r[length - 1] * params.lip_reflection;
+        let lip_output = match params.radiation {
+            Radiation::Constant => {
+                self.l_[length - 1] = self.r[length - 1] * params.lip_reflection;
+                None
+            }
+            Radiation::Filtered { .. } => {
+                self.lip_lowpass = lerp(self.r[length - 1], self.lip_lowpass, params.lip_pole);
+                self.l_[length - 1] = -FILTERED_REFLECTION * self.lip_lowpass;
+                Some((self.r[length - 1] + self.l_[length - 1]) * FILTERED_GAIN)
+            }
+        };
 
         for i in 0..length - 1 {
             let r = lerp(reflections.mouth[i], new_reflections.mouth[i], lambda);
@@ -497,12 +776,22 @@ impl State {
         );
         self.nose_r_[0] = r * self.nose_l[0] + (1.0 + r) * (self.l[i] + self.r[i - 1]);
 
-        for i in 0..length {
-            self.r[i] = (self.r_[i] * params.fade).clamp(-1.0, 1.0);
-            self.l[i] = (self.l_[i] * params.fade).clamp(-1.0, 1.0);
+        match params.losses {
+            Losses::Uniform => {
+                for i in 0..length {
+                    self.r[i] = (self.r_[i] * params.fade).clamp(-1.0, 1.0);
+                    self.l[i] = (self.l_[i] * params.fade).clamp(-1.0, 1.0);
+                }
+            }
+            Losses::Physical { .. } => apply_losses(
+                &new_reflections.mouth_losses,
+                [&mut self.r, &mut self.l],
+                [&self.r_, &self.l_],
+                [&mut self.last_r, &mut self.last_l],
+            ),
         }
 
-        self.r[length - 1]
+        lip_output.unwrap_or(self.r[length - 1])
     }
 
     pub fn process_nose(
@@ -512,7 +801,18 @@ impl State {
         first: f32,
     ) -> f32 {
         let length: usize = self.nose_r.len();
-        self.nose_l_[length - 1] = self.nose_r[length - 1] * params.lip_reflection;
+        let nose_output = match params.radiation {
+            Radiation::Constant => {
+                self.nose_l_[length - 1] = self.nose_r[length - 1] * params.lip_reflection;
+                None
+            }
+            Radiation::Filtered { .. } => {
+                self.nose_lowpass =
+                    lerp(self.nose_r[length - 1], self.nose_lowpass, params.nose_pole);
+                self.nose_l_[length - 1] = -FILTERED_REFLECTION * self.nose_lowpass;
+                Some((self.nose_r[length - 1] + self.nose_l_[length - 1]) * FILTERED_GAIN)
+            }
+        };
 
         let w = first * (self.nose_r[0] + self.nose_l[1]);
         self.nose_r_[1] = self.nose_r[0] - w;
@@ -524,12 +824,22 @@ impl State {
             self.nose_l_[i] = self.nose_l[i + 1] + w;
         }
 
-        for i in 0..length {
-            self.nose_r[i] = (self.nose_r_[i] * params.fade).clamp(-1.0, 1.0);
-            self.nose_l[i] = (self.nose_l_[i] * params.fade).clamp(-1.0, 1.0);
+        match params.losses {
+            Losses::Uniform => {
+                for i in 0..length {
+                    self.nose_r[i] = (self.nose_r_[i] * params.fade).clamp(-1.0, 1.0);
+                    self.nose_l[i] = (self.nose_l_[i] * params.fade).clamp(-1.0, 1.0);
+                }
+            }
+            Losses::Physical { .. } => apply_losses(
+                &reflections.nose_losses,
+                [&mut self.nose_r, &mut self.nose_l],
+                [&self.nose_r_, &self.nose_l_],
+                [&mut self.last_nose_r, &mut self.last_nose_l],
+            ),
         }
 
-        self.nose_r[length - 1]
+        nose_output.unwrap_or(self.nose_r[length - 1])
     }
 
     fn add_noise_at_index(&mut self, index: f32, noise: f32) {
@@ -545,11 +855,35 @@ impl State {
     }
 }
 
+fn apply_losses(
+    losses: &[SectionLoss],
+    output: [&mut Vec<f32>; 2],
+    input: [&Vec<f32>; 2],
+    last: [&mut Vec<f32>; 2],
+) {
+    for ((output, input), last) in output.into_iter().zip(input).zip(last) {
+        for i in 0..output.len() {
+            let SectionLoss { gain, lowpass } = losses[i];
+            let x = input[i];
+            output[i] = (gain * (x - lowpass * (x - last[i]))).clamp(-1.0, 1.0);
+            last[i] = x;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OtherParams {
     nose_start: usize,
     glottal_reflection: f32,
     lip_reflection: f32,
     fade: f32,
+    sample_rate: f32,
+    radiation: Radiation,
+    lip_pole: f32,
+    nose_pole: f32,
+    losses: Losses,
+    glottal_reflection_model: GlottalReflection,
 }
 
 impl OtherParams {
@@ -559,10 +893,158 @@ impl OtherParams {
             glottal_reflection: 0.75,
             lip_reflection: -0.85,
             fade: 0.999f32.powf(96000.0 / sample_rate),
+            sample_rate,
+            radiation: Radiation::Constant,
+            lip_pole: 0.0,
+            nose_pole: 0.0,
+            losses: Losses::Uniform,
+            glottal_reflection_model: GlottalReflection::Fixed(0.75),
+        }
+    }
+
+    /// Use `Tract::set_losses` to change it
+    pub fn losses(&self) -> Losses {
+        self.losses
+    }
+
+    pub fn glottal_reflection(&self) -> GlottalReflection {
+        self.glottal_reflection_model
+    }
+
+    pub fn set_glottal_reflection(&mut self, model: GlottalReflection) {
+        self.glottal_reflection_model = model;
+        if let GlottalReflection::Fixed(reflection) = model {
+            self.glottal_reflection = reflection;
+        }
+    }
+
+    /// Updates the glottal reflection if it depends on the tenseness.
+    pub fn update_tenseness(&mut self, tenseness: f32) {
+        if let GlottalReflection::Tenseness { lax, tense } = self.glottal_reflection_model {
+            self.glottal_reflection = lerp(lax, tense, tenseness);
         }
     }
+
+    pub fn radiation(&self) -> Radiation {
+        self.radiation
+    }
+
+    pub fn set_radiation(&mut self, radiation: Radiation) {
+        self.radiation = radiation;
+        if let Radiation::Filtered {
+            lip_cutoff,
+            nose_cutoff,
+        } = radiation
+        {
+            let pole = |cutoff: f32| (-2.0 * PI * cutoff / self.sample_rate).exp();
+            self.lip_pole = pole(lip_cutoff);
+            self.nose_pole = pole(nose_cutoff);
+        }
+    }
+}
+
+/// Model of the reflection and the radiation at the lips and the nostrils
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Radiation {
+    /// The ends reflect every frequency by -0.85 and the output is the wave arriving at the ends.
+    Constant,
+    /// The ends reflect through a one-pole lowpass, so low frequencies are reflected
+    /// almost completely and high frequencies radiate out.
+    /// The output is the transmitted wave, which is high-passed by the radiation.
+    /// The cutoffs are in Hz. A smaller opening has a higher cutoff.
+    Filtered { lip_cutoff: f32, nose_cutoff: f32 },
+}
+
+impl Radiation {
+    pub fn filtered() -> Self {
+        Radiation::Filtered {
+            lip_cutoff: 4000.0,
+            nose_cutoff: 6000.0,
+        }
+    }
+}
+
+/// How the sections of the tract move toward their targets
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Movement {
+    /// Each section moves at a constant speed of `Tract::movement_speed`
+    /// and stops at the target, as in the original Pink Trombone.
+    Linear,
+    /// Each section is pulled by the spring of its articulator,
+    /// so it accelerates, decelerates, and may overshoot the target.
+    Spring(Articulators),
+}
+
+impl Movement {
+    pub fn spring() -> Self {
+        Movement::Spring(Articulators::natural())
+    }
+}
+
+/// Target of a closed section for `Movement::Spring`
+const CLOSURE_OVERSHOOT: f32 = 0.2;
+
+/// Damping of the waves travelling through the tract
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Losses {
+    /// Every section is damped by the same factor at every frequency.
+    Uniform,
+    /// Each section is damped according to its diameter, so narrow sections lose more.
+    /// The rates are the amplitude decay per second of a section with diameter 1,
+    /// and `viscous` is measured at 1 kHz.
+    Physical {
+        /// Frequency-independent loss from the yielding walls,
+        /// which mainly widens the first formant
+        wall: f32,
+        /// Viscous and thermal loss, which grows with the frequency
+        /// and widens the higher formants
+        viscous: f32,
+    },
+}
+
+impl Losses {
+    pub fn physical() -> Self {
+        Losses::Physical {
+            wall: 80.0,
+            viscous: 6.0,
+        }
+    }
+}
+
+/// Diameter used for the losses of closed sections
+const MIN_LOSS_DIAMETER: f32 = 0.3;
+
+/// Reflection of the glottis seen from the tract
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GlottalReflection {
+    Fixed(f32),
+    /// A tense glottis stays closed longer and reflects more.
+    /// The reflection is interpolated between `lax` and `tense` by the tenseness.
+    /// The original Pink Trombone has `-0.8 + 1.6 * tenseness` commented out.
+    Tenseness {
+        lax: f32,
+        tense: f32,
+    },
 }
 
+impl GlottalReflection {
+    pub fn tenseness() -> Self {
+        GlottalReflection::Tenseness {
+            lax: 0.5,
+            tense: 0.9,
+        }
+    }
+}
+
+/// Reflection of the filtered radiation at DC
+const FILTERED_REFLECTION: f32 = 0.97;
+/// Make-up gain of the filtered radiation to roughly match the loudness of `Radiation::Constant`
+const FILTERED_GAIN: f32 = 4.0;
+
 fn move_towards(current: f32, target: f32, up: f32, down: f32) -> f32 {
     if current < target {
         target.min(current + up)
@@ -571,6 +1053,8 @@ fn move_towards(current: f32, target: f32, up: f32, down: f32) -> f32 {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct Transient {
     position: usize,
     delay: f32,
@@ -582,7 +1066,8 @@ impl Transient {
     const EXPONENT: f32 = 200.0;
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct Turbulence {
     index: f32,
     diameter: f32,
@@ -614,3 +1099,192 @@ impl Turbulence {
         }
     }
 }
+
+#[test]
+fn test_radiation() {
+    // Returns the mean power of the tract response in the bands in dB
+    fn band_levels(radiation: Radiation) -> (f32, f32) {
+        let mut benihora =
+            crate::Benihora::new(3.0, 48000.0, 1.0, 0, false, &TractGeometry::default());
+        benihora.tract.params.set_radiation(radiation);
+        let (response, sample_rate) = crate::tract_impulse_response(8192, &benihora);
+        assert!(response.iter().all(|x| x.is_finite()));
+
+        let level = |range: std::ops::Range<usize>| {
+            let power: f32 = range
+                .clone()
+                .step_by(50)
+                .map(|frequency| {
+                    let (mut re, mut im) = (0.0, 0.0);
+                    for (n, x) in response.iter().enumerate() {
+                        let w = 2.0 * PI * frequency as f32 * n as f32 / sample_rate;
+                        re += x * w.cos();
+                        im += x * w.sin();
+                    }
+                    re * re + im * im
+                })
+                .sum();
+            10.0 * (power / range.step_by(50).count() as f32).log10()
+        };
+        (level(200..1000), level(3000..5000))
+    }
+
+    let (low, high) = band_levels(Radiation::Constant);
+    let (filtered_low, filtered_high) = band_levels(Radiation::filtered());
+    // The radiation high-pass flattens the spectral tilt
+    assert!(filtered_high - filtered_low > high - low + 3.0);
+}
+
+#[test]
+fn test_losses() {
+    fn formants(
+        tongue: (f32, f32),
+        losses: Losses,
+        glottal_reflection: GlottalReflection,
+    ) -> Vec<crate::formant::Formant> {
+        let mut benihora =
+            crate::Benihora::new(3.0, 48000.0, 1.0, 0, false, &TractGeometry::default());
+        benihora.tract.set_losses(losses);
+        benihora.tract.params.set_radiation(Radiation::filtered());
+        benihora
+            .tract
+            .params
+            .set_glottal_reflection(glottal_reflection);
+        benihora.tract.params.update_tenseness(0.6);
+        benihora.tract.source.tongue = tongue;
+        benihora.tract.update_diameter();
+        benihora.tract.current_diameter = benihora.tract.target_diameter.clone();
+        benihora.tract.update_block(0.0);
+        let (response, sample_rate) = crate::tract_impulse_response(8192, &benihora);
+        crate::formant::find_formants(&response, sample_rate, 3000.0)
+    }
+
+    let lossless = Losses::Physical {
+        wall: 0.0,
+        viscous: 0.0,
+    };
+    // Bandwidths measured on real vowels are mostly 40-150 Hz for F1 and 50-300 Hz for F2
+    // (Fant 1972, Hawks and Miller 1995).
+    for tongue in [(12.9, 2.43), (22.8, 2.05), (17.5, 2.9)] {
+        let measured = formants(tongue, Losses::physical(), GlottalReflection::tenseness());
+        let b1 = measured[0].bandwidth;
+        let b2 = measured[1].bandwidth;
+        assert!((40.0..150.0).contains(&b1), "{:?}", measured);
+        assert!((50.0..300.0).contains(&b2), "{:?}", measured);
+
+        let lossless = formants(tongue, lossless, GlottalReflection::tenseness());
+        assert!(lossless[0].bandwidth < b1);
+    }
+
+    // A lax glottis reflects less and widens the formants
+    let tense = formants(
+        (12.9, 2.43),
+        Losses::physical(),
+        GlottalReflection::Fixed(0.9),
+    );
+    let lax = formants(
+        (12.9, 2.43),
+        Losses::physical(),
+        GlottalReflection::Fixed(0.5),
+    );
+    assert!(tense[0].bandwidth < lax[0].bandwidth);
+}
+
+#[test]
+fn test_movement() {
+    use crate::articulator::Spring;
+
+    let geometry = TractGeometry::default();
+    let new_tract = |movement: Movement| {
+        let mut tract = Tract::new(2, 48000.0, 0, &geometry);
+        tract.set_movement(movement);
+        tract.source.tongue = tract.source.tongue_clamp(22.8, 2.05);
+        tract.update_diameter();
+        tract.current_diameter = tract.target_diameter.clone();
+        tract.source.tongue = tract.source.tongue_clamp(12.9, 2.43);
+        tract.update_diameter();
+        tract
+    };
+    let distance = |tract: &Tract| {
+        tract
+            .current_diameter
+            .mouth
+            .iter()
+            .zip(&tract.target_diameter.mouth)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max)
+    };
+
+    let mut linear = new_tract(Movement::Linear);
+    let mut spring = new_tract(Movement::spring());
+    let start = distance(&spring);
+    linear.update_block(0.002);
+    spring.update_block(0.002);
+    // Starts slowly instead of at full speed
+    assert!(start - distance(&spring) < start - distance(&linear));
+
+    // Settles on the target
+    for _ in 0..50 {
+        spring.update_block(0.01);
+    }
+    assert!(distance(&spring) < 0.01, "{}", distance(&spring));
+
+    // Low damping overshoots
+    let soft = Spring::new(7.0, 0.3);
+    let mut tract = new_tract(Movement::Spring(Articulators {
+        tongue: soft,
+        tip: soft,
+        lips: soft,
+        velum: soft,
+    }));
+    let (index, _) = tract
+        .target_diameter
+        .mouth
+        .iter()
+        .zip(&tract.current_diameter.mouth)
+        .enumerate()
+        .max_by(|(_, a), (_, b)| (a.0 - a.1).abs().total_cmp(&(b.0 - b.1).abs()))
+        .unwrap();
+    let start = tract.current_diameter.mouth[index];
+    let target = tract.target_diameter.mouth[index];
+    let mut overshoot = 0.0f32;
+    for _ in 0..50 {
+        tract.update_block(0.01);
+        let d = tract.current_diameter.mouth[index];
+        overshoot = overshoot.max((d - target) * (target - start).signum());
+    }
+    assert!(0.05 < overshoot / (target - start).abs());
+
+    // A closure is reached even by critically damped springs
+    let critical = Spring::new(10.0, 1.0);
+    let mut tract = new_tract(Movement::Spring(Articulators {
+        tongue: critical,
+        tip: critical,
+        lips: critical,
+        velum: critical,
+    }));
+    tract.source.other_constrictions = vec![(41.0, 0.0)];
+    tract.update_diameter();
+    for _ in 0..20 {
+        tract.update_block(0.01);
+    }
+    assert!(tract.current_diameter.mouth.iter().any(|&d| d == 0.0));
+}
+
+#[test]
+fn test_geometry() {
+    for geometry in [TractGeometry::new(60, 38), TractGeometry::new(30, 3)] {
+        let mut benihora = crate::Benihora::new(3.0, 48000.0, 1.0, 0, false, &geometry);
+        let source = &mut benihora.tract.source;
+        // The default tongue is at the same relative place
+        let relative = source.tongue.0 / geometry.mouth_length as f32;
+        assert!((relative - DEFAULT_TONGUE.0 / DEFAULT_MOUTH_LENGTH as f32).abs() < 1.0e-6);
+        source.other_constrictions = vec![(source.scale_index(41.0), 1.0)];
+
+        let output: Vec<f32> = (0..4800)
+            .map(|_| benihora.process(140.0, 0.6, 1.0, 1.0, 1.0))
+            .collect();
+        assert!(output.iter().all(|x| x.is_finite()));
+        assert!(output.iter().any(|x| x.abs() > 1.0e-3));
+    }
+}
@@ -1,5 +1,7 @@
 use crate::rand_f32;
 
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Wiggle {
     frequency: f32,
     rand: u32,